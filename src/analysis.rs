@@ -0,0 +1,671 @@
+use crate::common::{ms_to_samples, sum_f64};
+use crate::dsp::{deconvolve, hilbert_magnitude, local_rms, power_spectrum};
+
+/// Half width of the window around the peak treated as direct sound [ms]
+pub const DIRECT_SOUND_WINDOW: f32 = 2.5;
+
+/// Start of the late tail after the direct sound [ms]
+pub const LATE_TAIL_START: f32 = 80.0;
+
+/// Window of the RMS envelope removed before tail statistics [ms]
+pub const ENVELOPE_WINDOW: f32 = 10.0;
+
+/// Part of the response at its end used as the noise floor estimate
+const NOISE_FLOOR_SEGMENT: f32 = 0.1;
+
+/// Level below the peak where the direct sound starts, ISO 3382-1 [dB]
+const ONSET_THRESHOLD: f32 = -20.0;
+
+/// Fraction of Gaussian noise samples outside one standard deviation,
+/// `erfc(1 / sqrt(2))`, normalizes the echo density to 1 for diffuse tails
+const GAUSSIAN_OUTLIER_FRACTION: f64 = 0.317_310_5;
+
+/// Regularization of sweep deconvolution relative to the peak power of the
+/// sweep spectrum, limits the boost outside the swept band to 50 dB
+const SWEEP_REGULARIZATION: f32 = 1e-5;
+
+/// Octave band centers used for the spectral tilt and air absorption [Hz]
+pub(crate) const OCTAVE_BANDS: [f32; 7] =
+    [125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0];
+
+/// Acoustic parameters measured from an impulse response.
+///
+/// rt60: reverberation time, extrapolated from T20 [ms]
+/// edt: early decay time [ms]
+/// drr: direct to reverberant energy ratio [dB]
+/// c50: clarity for speech [dB]
+/// c80: clarity for music [dB]
+/// spectral_tilt: slope of octave band energies [dB/octave]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    pub rt60: Option<f32>,
+    pub edt: Option<f32>,
+    pub drr: f32,
+    pub c50: f32,
+    pub c80: f32,
+    pub spectral_tilt: Option<f32>,
+}
+
+impl Metrics {
+    /// Measure all the parameters of the impulse response
+    pub fn measure(ir: &[f32], sample_rate: u32) -> Self {
+        Self {
+            rt60: rt60(ir, sample_rate),
+            edt: edt(ir, sample_rate),
+            drr: drr(ir, sample_rate),
+            c50: clarity(ir, sample_rate, 50.0),
+            c80: clarity(ir, sample_rate, 80.0),
+            spectral_tilt: spectral_tilt(ir, sample_rate),
+        }
+    }
+}
+
+/// Differences between two impulse responses.
+///
+/// correlation: normalized cross-correlation of peak aligned responses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub reference: Metrics,
+    pub candidate: Metrics,
+    pub correlation: f32,
+}
+
+/// Measure both impulse responses and correlate them
+pub fn compare(
+    reference: &[f32],
+    candidate: &[f32],
+    sample_rate: u32,
+) -> Comparison {
+    Comparison {
+        reference: Metrics::measure(reference, sample_rate),
+        candidate: Metrics::measure(candidate, sample_rate),
+        correlation: correlation(reference, candidate),
+    }
+}
+
+/// Amplitude statistics of the late tail with its decay envelope removed.
+///
+/// crest_factor: peak to RMS ratio, around 4 for Gaussian noise of a second
+/// kurtosis: fourth moment to squared variance, 3 for Gaussian noise
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TailStatistics {
+    pub crest_factor: f32,
+    pub kurtosis: f32,
+}
+
+/// Statistics of the tail starting `LATE_TAIL_START` after the direct sound,
+/// measured fields are near Gaussian while thinned tails get spiky. None when
+/// the response ends before the tail starts or the tail is silent.
+pub fn tail_statistics(ir: &[f32], sample_rate: u32) -> Option<TailStatistics> {
+    let start = (peak_index(ir) + ms_to_samples(LATE_TAIL_START, sample_rate))
+        .min(ir.len());
+    let tail = flattened(&ir[start..], sample_rate);
+    if tail.iter().all(|&x| x == 0.0) {
+        return None;
+    }
+    Some(TailStatistics {
+        crest_factor: crest_factor(&tail),
+        kurtosis: kurtosis(&tail),
+    })
+}
+
+/// Peak to RMS ratio
+pub fn crest_factor(data: &[f32]) -> f32 {
+    let peak = data.iter().fold(0.0_f32, |m, x| m.max(x.abs()));
+    let rms = (energy(data) / data.len() as f64).sqrt();
+    (peak as f64 / rms) as f32
+}
+
+/// Kurtosis (not excess), E[x^4] / E[x^2]^2 around zero
+pub fn kurtosis(data: &[f32]) -> f32 {
+    let n = data.len() as f64;
+    let second = energy(data) / n;
+    let fourth: f64 = data.iter().map(|&x| (x as f64).powi(4)).sum::<f64>() / n;
+    (fourth / second.powi(2)) as f32
+}
+
+/// Signal divided by its local RMS, removes decay before statistics
+pub(crate) fn flattened(data: &[f32], sample_rate: u32) -> Vec<f32> {
+    let window = ms_to_samples(ENVELOPE_WINDOW, sample_rate);
+    data.iter()
+        .zip(local_rms(data, window))
+        .map(|(&x, rms)| if rms > 0.0 { x / rms } else { 0.0 })
+        .collect()
+}
+
+/// Noise floor of a measured impulse response.
+///
+/// start: sample where the decay sinks into the noise
+/// level: noise floor relative to the envelope peak [dB]
+/// decay_rate: slope of the decay before the noise floor [dB/ms]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseFloor {
+    pub start: usize,
+    pub level: f32,
+    pub decay_rate: f32,
+}
+
+/// Find where the decay of a measured impulse response meets its noise floor.
+///
+/// Floor level is the mean energy of the last tenth of the response, a line
+/// is fitted to the RMS envelope [dB] from 5 dB below its peak down to 10 dB
+/// above the floor and the floor starts where that line crosses it. None for
+/// responses decaying into digital silence or not decaying at all.
+pub fn noise_floor(ir: &[f32], sample_rate: u32) -> Option<NoiseFloor> {
+    let window = ms_to_samples(ENVELOPE_WINDOW, sample_rate);
+    let envelope = local_rms(ir, window);
+    let peak = envelope.iter().fold(0.0_f32, |m, &x| m.max(x)) as f64;
+    let segment = ((ir.len() as f32 * NOISE_FLOOR_SEGMENT) as usize).max(1);
+    let floor_energy =
+        energy(&ir[ir.len().saturating_sub(segment)..]) / segment as f64;
+    if peak == 0.0 || floor_energy == 0.0 {
+        return None;
+    }
+    let level = 10.0 * (floor_energy / peak.powi(2)).log10();
+
+    let points: Vec<(f64, f64)> = envelope
+        .iter()
+        .map(|&x| 20.0 * (x as f64 / peak).log10())
+        .enumerate()
+        .skip_while(|&(_, db)| db > -5.0)
+        .take_while(|&(_, db)| db >= level + 10.0)
+        .map(|(i, db)| (i as f64, db))
+        .collect();
+    match linear_fit(&points) {
+        Some((slope, intercept)) if slope < 0.0 => {
+            let start = ((level - intercept) / slope).clamp(0.0, ir.len() as f64);
+            Some(NoiseFloor {
+                start: start as usize,
+                level: level as f32,
+                decay_rate: (slope * sample_rate as f64 / 1000.0) as f32,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Effect of storing impulse response as integer samples.
+///
+/// snr: signal to quantization noise ratio [dB]
+/// decay_range: depth of the energy decay curve before the response
+/// vanishes, unquantized [dB]
+/// decay_range_loss: part of the decay range lost because tail samples
+/// round to zero [dB]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizationImpact {
+    pub snr: f32,
+    pub decay_range: f32,
+    pub decay_range_loss: f32,
+}
+
+/// Preview quantization to `bits` bit integers (16 or 24) the way WAV
+/// export scales samples, errors on bit depths outside 1..=32
+pub fn quantization_impact(
+    ir: &[f32],
+    bits: u32,
+) -> Result<QuantizationImpact, String> {
+    if !(1..=32).contains(&bits) {
+        return Err(format!("Bit depth {} is out of range [1, 32]", bits));
+    }
+    // One bit leaves no positive level, sign and zero are kept at least
+    let max_amplitude = ((1_i64 << (bits - 1)) - 1).max(1) as f32;
+    let quantized: Vec<f32> = ir
+        .iter()
+        .map(|&x| (x * max_amplitude).round() / max_amplitude)
+        .collect();
+    let noise: Vec<f32> = ir.iter().zip(&quantized).map(|(x, q)| x - q).collect();
+    let original_range = decay_range(ir);
+
+    Ok(QuantizationImpact {
+        snr: (10.0 * (energy(ir) / energy(&noise)).log10()) as f32,
+        decay_range: original_range,
+        // Rounding up the last samples may even extend the range a bit
+        decay_range_loss: (original_range - decay_range(&quantized)).max(0.0),
+    })
+}
+
+/// Deepest finite level of the energy decay curve [dB], positive
+fn decay_range(ir: &[f32]) -> f32 {
+    let edc = energy_decay_curve(ir);
+    ir.iter()
+        .rposition(|&x| x != 0.0)
+        .map_or(0.0, |last| -edc[last] as f32)
+}
+
+/// Index of the sample with the largest magnitude (direct sound)
+pub fn peak_index(ir: &[f32]) -> usize {
+    ir.iter()
+        .enumerate()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Index of the direct sound onset, first sample rising to `ONSET_THRESHOLD`
+/// below the peak (ISO 3382-1), None for empty or silent responses
+pub fn find_onset(ir: &[f32]) -> Option<usize> {
+    let peak = ir.get(peak_index(ir))?.abs();
+    if peak == 0.0 {
+        return None;
+    }
+    let threshold = peak * 10.0_f32.powf(ONSET_THRESHOLD / 20.0);
+    ir.iter().position(|x| x.abs() >= threshold)
+}
+
+/// Temporal envelope, magnitude of the analytic signal smoothed with the
+/// centered moving average of `smoothing` [ms], 0 keeps it unsmoothed
+pub fn envelope(ir: &[f32], sample_rate: u32, smoothing: f32) -> Vec<f32> {
+    let magnitude = hilbert_magnitude(ir);
+    let half = ms_to_samples(smoothing, sample_rate) / 2;
+    if half == 0 {
+        return magnitude;
+    }
+    let mut cumulative = Vec::with_capacity(magnitude.len() + 1);
+    cumulative.push(0.0_f64);
+    for &x in &magnitude {
+        cumulative.push(cumulative.last().unwrap() + x as f64);
+    }
+    (0..magnitude.len())
+        .map(|i| {
+            let start = i.saturating_sub(half);
+            let end = (i + half + 1).min(magnitude.len());
+            ((cumulative[end] - cumulative[start]) / (end - start) as f64) as f32
+        })
+        .collect()
+}
+
+/// Schroeder backward integrated energy decay curve [dB], 0 dB at the start
+pub fn energy_decay_curve(ir: &[f32]) -> Vec<f64> {
+    let mut edc = vec![0.0; ir.len()];
+    let mut remaining = 0.0;
+    for (i, &x) in ir.iter().enumerate().rev() {
+        remaining += (x as f64).powi(2);
+        edc[i] = remaining;
+    }
+    let total = remaining;
+    edc.iter().map(|&e| 10.0 * (e / total).log10()).collect()
+}
+
+/// Reverberation time [ms], extrapolated from the -5 to -25 dB decay (T20)
+pub fn rt60(ir: &[f32], sample_rate: u32) -> Option<f32> {
+    decay_time(&energy_decay_curve(ir), sample_rate, -5.0, -25.0)
+}
+
+/// Early decay time [ms], six times the time the decay curve takes to drop
+/// by the first 10 dB after the direct sound
+pub fn edt(ir: &[f32], sample_rate: u32) -> Option<f32> {
+    let edc = energy_decay_curve(ir);
+    let peak = peak_index(ir);
+    edc.iter()
+        .skip(peak)
+        .position(|&level| level - edc[peak] <= -10.0)
+        .map(|samples| (6.0 * samples as f64 / sample_rate as f64 * 1000.0) as f32)
+}
+
+/// Direct to reverberant energy ratio [dB], direct sound is the peak with
+/// `DIRECT_SOUND_WINDOW` on both sides.
+///
+/// Generator output holds energies, measured on their square roots it equals
+/// the DRR the generators target when ITDG is at least the window.
+pub fn drr(ir: &[f32], sample_rate: u32) -> f32 {
+    let peak = peak_index(ir);
+    let window = ms_to_samples(DIRECT_SOUND_WINDOW, sample_rate);
+    let start = peak.saturating_sub(window);
+    let end = (peak + window + 1).min(ir.len());
+    let direct = energy(&ir[start..end]);
+    let reverberant = energy(&ir[end..]);
+    (10.0 * (direct / reverberant).log10()) as f32
+}
+
+/// Clarity [dB], early (up to `time` [ms] after the direct sound) to late
+/// energy ratio, C50 and C80 are the usual choices
+pub fn clarity(ir: &[f32], sample_rate: u32, time: f32) -> f32 {
+    let peak = peak_index(ir);
+    let split = (peak + ms_to_samples(time, sample_rate)).min(ir.len());
+    let early = energy(&ir[peak..split]);
+    let late = energy(&ir[split..]);
+    (10.0 * (early / late).log10()) as f32
+}
+
+/// Spectral tilt [dB/octave], least squares slope of octave band energies,
+/// None for responses too short to fill two octave bands
+pub fn spectral_tilt(ir: &[f32], sample_rate: u32) -> Option<f32> {
+    let spectrum = power_spectrum(ir);
+    if spectrum.len() < 2 {
+        return None;
+    }
+    let bin_width = sample_rate as f32 / ir.len() as f32;
+    let nyquist = sample_rate as f32 / 2.0;
+
+    let mut points = Vec::new();
+    for (octave, &center) in OCTAVE_BANDS.iter().enumerate() {
+        let low = center / 2.0_f32.sqrt();
+        let high = center * 2.0_f32.sqrt();
+        if high > nyquist {
+            break;
+        }
+        let first = (low / bin_width).ceil() as usize;
+        let last = ((high / bin_width).floor() as usize).min(spectrum.len() - 1);
+        if first > last {
+            continue;
+        }
+        let band_energy = sum_f64(&spectrum[first..=last]);
+        if band_energy > 0.0 {
+            points.push((octave as f64, 10.0 * band_energy.log10()));
+        }
+    }
+    linear_fit(&points).map(|(slope, _)| slope as f32)
+}
+
+/// Normalized cross-correlation of the responses aligned at their peaks
+pub fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let (a, b) = (&a[peak_index(a)..], &b[peak_index(b)..]);
+    let len = a.len().min(b.len());
+    let (a, b) = (&a[..len], &b[..len]);
+    let cross: f64 = a.iter().zip(b).map(|(&x, &y)| (x * y) as f64).sum();
+    (cross / (energy(a) * energy(b)).sqrt()) as f32
+}
+
+/// Impulse response measured with a sine sweep (exponential sine sweep
+/// method, Farina), `recording` of the room played `sweep` at the same
+/// sample rate.
+///
+/// Recording should start with the playback and continue after the sweep
+/// ends for at least the reverberation time, the response has
+/// `recording.len() - sweep.len() + 1` samples. Harmonic distortion products
+/// of exponential sweeps arrive before the linear response and are dropped.
+pub fn deconvolve_sweep(recording: &[f32], sweep: &[f32]) -> Vec<f32> {
+    if sweep.is_empty() || recording.len() < sweep.len() {
+        panic!("Recording should be at least as long as the non-empty sweep")
+    };
+    let mut ir = deconvolve(recording, sweep, SWEEP_REGULARIZATION);
+    ir.truncate(recording.len() - sweep.len() + 1);
+    ir
+}
+
+/// Normalized echo density profile (Abel & Huang), one value per sample.
+///
+/// Fraction of samples within the Hann window of `window` [ms] centered on
+/// each sample that lie outside one standard deviation of the window,
+/// relative to Gaussian noise: values near 0 for isolated reflections and
+/// around 1 once the response is diffuse. Windows are cut at both ends.
+pub fn echo_density(ir: &[f32], sample_rate: u32, window: f32) -> Vec<f32> {
+    let half = ms_to_samples(window, sample_rate) / 2;
+    let weights: Vec<f64> = (0..=2 * half)
+        .map(|i| {
+            let phase =
+                std::f64::consts::PI * (i + 1) as f64 / (2 * half + 2) as f64;
+            phase.sin().powi(2)
+        })
+        .collect();
+    (0..ir.len())
+        .map(|t| {
+            let start = t.saturating_sub(half);
+            let end = (t + half + 1).min(ir.len());
+            let window = &weights[start + half - t..end + half - t];
+            let samples = &ir[start..end];
+            let total: f64 = window.iter().sum();
+            let power = samples
+                .iter()
+                .zip(window)
+                .map(|(&x, w)| w * (x as f64).powi(2))
+                .sum::<f64>()
+                / total;
+            let sigma = power.sqrt();
+            let outside: f64 = samples
+                .iter()
+                .zip(window)
+                .filter(|(&x, _)| (x as f64).abs() > sigma)
+                .map(|(_, w)| w)
+                .sum();
+            (outside / total / GAUSSIAN_OUTLIER_FRACTION) as f32
+        })
+        .collect()
+}
+
+/// Interaural (inter-channel) cross-correlation coefficient, maximum of the
+/// normalized cross-correlation within +-1 ms lag
+pub fn iacc(a: &[f32], b: &[f32], sample_rate: u32) -> f32 {
+    let max_lag = ms_to_samples(1.0, sample_rate) as isize;
+    let norm = (energy(a) * energy(b)).sqrt();
+    (-max_lag..=max_lag)
+        .map(|lag| {
+            let (a, b) = if lag >= 0 {
+                (&a[(lag as usize).min(a.len())..], b)
+            } else {
+                (a, &b[(-lag as usize).min(b.len())..])
+            };
+            let cross: f64 = a.iter().zip(b).map(|(&x, &y)| (x * y) as f64).sum();
+            (cross / norm).abs()
+        })
+        .fold(0.0, f64::max) as f32
+}
+
+/// Time to decay by 60 dB [ms], from a line fitted to the decay curve
+/// between `start` and `end` levels [dB]
+fn decay_time(edc: &[f64], sample_rate: u32, start: f64, end: f64) -> Option<f32> {
+    let points: Vec<(f64, f64)> = edc
+        .iter()
+        .enumerate()
+        .skip_while(|(_, &level)| level > start)
+        .take_while(|(_, &level)| level >= end)
+        .map(|(i, &level)| (i as f64, level))
+        .collect();
+    match linear_fit(&points) {
+        Some((slope, _)) if slope < 0.0 => {
+            Some((-60.0 / slope / sample_rate as f64 * 1000.0) as f32)
+        }
+        _ => None,
+    }
+}
+
+/// Least squares line fit, returns (slope, intercept)
+pub(crate) fn linear_fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some((slope, mean_y - slope * mean_x))
+}
+
+fn energy(data: &[f32]) -> f64 {
+    data.iter().map(|&x| (x as f64).powi(2)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential_decay_metrics() {
+        // Alternating sign exponential decay with 60 dB drop in 500 ms
+        let sample_rate = 16000;
+        let decay = -60.0 / (0.5 * sample_rate as f32);
+        let ir: Vec<f32> = (0..sample_rate)
+            .map(|i| {
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                sign * 10.0_f32.powf(decay * i as f32 / 20.0)
+            })
+            .collect();
+
+        let metrics = Metrics::measure(&ir, sample_rate);
+        let rt60 = metrics.rt60.unwrap();
+        let edt = metrics.edt.unwrap();
+        assert!((rt60 - 500.0).abs() < 5.0, "rt60: {}", rt60);
+        assert!((edt - 500.0).abs() < 5.0, "edt: {}", edt);
+        assert!(metrics.c50 < metrics.c80);
+        assert!((correlation(&ir, &ir) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_drr_of_generated() {
+        use crate::{ImpulseResponseGenerator, ImpulseResponseSimple};
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        let rir = ImpulseResponseSimple::new(500.0, 50.0, 4.0, 100.0, -3.0);
+        let energies = rir.generate_with_rng(16000, &mut StdRng::seed_from_u64(5));
+        let amplitudes: Vec<f32> = energies.iter().map(|x| x.sqrt()).collect();
+        let drr = drr(&amplitudes, 16000);
+        assert!((drr + 3.0).abs() <= 0.5, "drr: {}", drr);
+    }
+
+    #[test]
+    fn test_tail_statistics_of_short_responses() {
+        // 50 ms at 16 kHz ends before the late tail
+        let short: Vec<f32> = (0..800).map(|i| 0.99_f32.powi(i)).collect();
+        assert_eq!(tail_statistics(&short, 16000), None);
+        let mut silent = vec![0.0; 3200];
+        silent[0] = 1.0;
+        assert_eq!(tail_statistics(&silent, 16000), None);
+
+        let long: Vec<f32> = (0..3200)
+            .map(|i| if i % 3 == 0 { 0.999_f32.powi(i) } else { 0.0 })
+            .collect();
+        let statistics = tail_statistics(&long, 16000).unwrap();
+        assert!(statistics.crest_factor.is_finite() && statistics.kurtosis > 3.0);
+    }
+
+    #[test]
+    fn test_metrics_of_empty_responses() {
+        assert_eq!(spectral_tilt(&[], 16000), None);
+        assert_eq!(spectral_tilt(&[1.0], 16000), None);
+        assert_eq!(spectral_tilt(&[1.0, 0.5, 0.25], 16000), None);
+
+        let metrics = Metrics::measure(&[], 16000);
+        assert_eq!((metrics.rt60, metrics.edt), (None, None));
+        assert_eq!(metrics.spectral_tilt, None);
+    }
+
+    #[test]
+    fn test_find_onset() {
+        // Low level noise before a direct sound rising over two samples
+        let mut ir: Vec<f32> = (0..200).map(|i| 1e-3 * (i as f32).sin()).collect();
+        ir[120] = 0.3;
+        ir[121] = 1.0;
+        ir[125] = 0.5;
+        assert_eq!(find_onset(&ir), Some(120));
+        assert_eq!(find_onset(&[0.0; 16]), None);
+        assert_eq!(find_onset(&[]), None);
+    }
+
+    #[test]
+    fn test_envelope() {
+        // Exponentially decaying 1 kHz tone, envelope follows the decay
+        let sample_rate = 16000;
+        let amplitude = |i: usize| (-(i as f32) / 2000.0).exp();
+        let ir: Vec<f32> = (0..sample_rate as usize)
+            .map(|i| {
+                amplitude(i)
+                    * (2.0 * std::f32::consts::PI * 1000.0 * i as f32
+                        / sample_rate as f32)
+                        .sin()
+            })
+            .collect();
+        for smoothing in [0.0, 2.0] {
+            let result = envelope(&ir, sample_rate, smoothing);
+            assert_eq!(result.len(), ir.len());
+            for i in (1000..15000).step_by(500) {
+                assert!(
+                    (result[i] - amplitude(i)).abs() < 0.01,
+                    "{}: {} vs {}",
+                    i,
+                    result[i],
+                    amplitude(i)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_noise_floor() {
+        // 60 dB drop in 500 ms on top of noise 60 dB below the peak
+        let sample_rate = 16000;
+        let decay = -60.0 / (0.5 * sample_rate as f32);
+        let ir: Vec<f32> = (0..sample_rate)
+            .map(|i| {
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                let noise = if i % 3 == 0 { 1e-3 } else { -1e-3 };
+                sign * 10.0_f32.powf(decay * i as f32 / 20.0) + noise
+            })
+            .collect();
+        let floor = noise_floor(&ir, sample_rate).unwrap();
+        assert!((floor.level + 60.0).abs() < 1.0, "{:?}", floor);
+        assert!((floor.decay_rate + 0.12).abs() < 0.01, "{:?}", floor);
+        assert!((floor.start as i64 - 8000).abs() < 800, "{:?}", floor);
+
+        let silent: Vec<f32> = ir
+            .iter()
+            .take(8000)
+            .copied()
+            .chain(vec![0.0; 8000])
+            .collect();
+        assert!(noise_floor(&silent, sample_rate).is_none());
+    }
+
+    #[test]
+    fn test_quantization_impact() {
+        let ir: Vec<f32> = (0..16000)
+            .map(|i| 10.0_f32.powf(-100.0 * i as f32 / 16000.0 / 20.0))
+            .collect();
+        let low = quantization_impact(&ir, 16).unwrap();
+        let high = quantization_impact(&ir, 24).unwrap();
+        assert!(low.snr < high.snr);
+        assert!(low.decay_range_loss > high.decay_range_loss);
+        assert!(high.decay_range_loss.abs() < 0.1, "{:?}", high);
+
+        assert!(quantization_impact(&ir, 1).unwrap().snr.is_finite());
+        assert!(quantization_impact(&ir, 32).is_ok());
+        assert!(quantization_impact(&ir, 0).is_err());
+        assert!(quantization_impact(&ir, 33).is_err());
+    }
+
+    #[test]
+    fn test_deconvolve_sweep() {
+        let sweep = crate::dsp::ess(50.0, 7000.0, 500.0, 16000);
+        let ir: Vec<f32> = (0..1600)
+            .map(|i| match i {
+                40 => 1.0,
+                _ if i > 60 && i % 7 == 0 => 0.3 * (-(i as f32) / 300.0).exp(),
+                _ => 0.0,
+            })
+            .collect();
+        let mut recording = crate::dsp::convolve(&sweep, &ir);
+        recording.truncate(sweep.len() + ir.len() - 1);
+
+        let measured = deconvolve_sweep(&recording, &sweep);
+        assert_eq!(measured.len(), ir.len());
+        assert_eq!(peak_index(&measured), 40);
+        let correlation = correlation(&measured, &ir);
+        assert!(correlation > 0.9, "correlation: {}", correlation);
+    }
+
+    #[test]
+    fn test_echo_density() {
+        use ndarray_rand::rand::{rngs::StdRng, Rng, SeedableRng};
+        use ndarray_rand::rand_distr::StandardNormal;
+
+        let sample_rate = 16000;
+        let mut rng = StdRng::seed_from_u64(4);
+        let noise: Vec<f32> =
+            (0..8000).map(|_| rng.sample(StandardNormal)).collect();
+        let density = echo_density(&noise, sample_rate, 20.0);
+        assert_eq!(density.len(), noise.len());
+        let mean = density[1000..7000].iter().sum::<f32>() / 6000.0;
+        assert!((mean - 1.0).abs() < 0.1, "noise: {}", mean);
+
+        // Isolated reflections every 10 ms
+        let sparse: Vec<f32> = (0..8000)
+            .map(|i| if i % 160 == 0 { 1.0 } else { 0.0 })
+            .collect();
+        let density = echo_density(&sparse, sample_rate, 20.0);
+        assert!(density[1000..7000].iter().all(|&d| d < 0.1));
+    }
+}
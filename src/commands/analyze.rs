@@ -0,0 +1,182 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::Args;
+use storir::analysis::Metrics;
+use storir::common::{is_supported_sample_rate, MAX_SAMPLE_RATE, MIN_SAMPLE_RATE};
+use storir::wav::read_wav;
+
+use super::{
+    csv_field, find_wav_files, json_number, json_string, metrics_json,
+    ReportFormat, EXIT_IO_ERROR,
+};
+
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    /// Folder with impulse responses (wav), searched recursively
+    #[arg(long)]
+    dir: PathBuf,
+    /// Report file, JSON for `.json` extension and CSV otherwise, results
+    /// are printed when omitted
+    #[arg(long)]
+    report: Option<PathBuf>,
+    /// Print a table or JSON (one object per file)
+    #[arg(long, value_enum, default_value = "text")]
+    output_format: ReportFormat,
+}
+
+/// Measured metrics of one file
+struct Row {
+    path: PathBuf,
+    sample_rate: u32,
+    /// Duration [ms]
+    duration: f32,
+    metrics: Metrics,
+}
+
+impl Row {
+    /// Read and measure the file, errors on unreadable files, empty audio
+    /// and sample rates out of the supported range
+    fn measure(path: PathBuf) -> Result<Self, String> {
+        let (impulse, sample_rate) = read_wav(&path).map_err(|e| e.to_string())?;
+        if impulse.is_empty() {
+            return Err("no samples".to_string());
+        }
+        if !is_supported_sample_rate(sample_rate) {
+            return Err(format!(
+                "sample rate {} is out of supported range [{}, {}]",
+                sample_rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+            ));
+        }
+        Ok(Self {
+            duration: impulse.len() as f32 / sample_rate as f32 * 1000.0,
+            metrics: Metrics::measure(&impulse, sample_rate),
+            path,
+            sample_rate,
+        })
+    }
+
+    fn json(&self) -> String {
+        format!(
+            "{{\"file\":{},\"sample_rate\":{},\"duration\":{},\"measured\":{}}}",
+            json_string(&self.path.display().to_string()),
+            self.sample_rate,
+            json_number(Some(self.duration)),
+            metrics_json(&self.metrics)
+        )
+    }
+}
+
+pub fn run(args: AnalyzeArgs) {
+    let files = match find_wav_files(&args.dir) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", args.dir.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+
+    let mut rows = Vec::with_capacity(files.len());
+    let mut failed = false;
+    // Files that fail are reported and skipped, the rest are still measured
+    for path in files {
+        let display = path.display().to_string();
+        match Row::measure(path) {
+            Ok(row) => rows.push(row),
+            Err(e) => {
+                eprintln!("Skipping '{}': {}", display, e);
+                failed = true;
+            }
+        }
+    }
+
+    match &args.report {
+        Some(report) => {
+            if let Err(e) = write_report(report, &rows) {
+                eprintln!("Error writing '{}': {}", report.display(), e);
+                process::exit(EXIT_IO_ERROR);
+            }
+            match args.output_format {
+                ReportFormat::Text => println!(
+                    "Analyzed {} files, report written to '{}'",
+                    rows.len(),
+                    report.display()
+                ),
+                ReportFormat::Json => println!(
+                    "{{\"files\":{},\"report\":{}}}",
+                    rows.len(),
+                    json_string(&report.display().to_string())
+                ),
+            }
+        }
+        None => match args.output_format {
+            ReportFormat::Text => print_table(&rows),
+            ReportFormat::Json => {
+                rows.iter().for_each(|row| println!("{}", row.json()))
+            }
+        },
+    }
+    if failed {
+        process::exit(EXIT_IO_ERROR);
+    }
+}
+
+fn print_table(rows: &[Row]) {
+    let format_value = |value: Option<f32>| {
+        value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v))
+    };
+    println!(
+        "{:<40} {:>10} {:>10} {:>10} {:>10}",
+        "file", "rt60 [ms]", "edt [ms]", "drr [dB]", "c50 [dB]"
+    );
+    for row in rows {
+        println!(
+            "{:<40} {:>10} {:>10} {:>10} {:>10}",
+            row.path.display(),
+            format_value(row.metrics.rt60),
+            format_value(row.metrics.edt),
+            format_value(Some(row.metrics.drr)),
+            format_value(Some(row.metrics.c50))
+        );
+    }
+}
+
+fn write_report(path: &Path, rows: &[Row]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    let json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    if json {
+        let rows: Vec<String> = rows.iter().map(Row::json).collect();
+        writeln!(writer, "[{}]", rows.join(","))?;
+    } else {
+        // Missing values are left empty
+        let cell =
+            |value: Option<f32>| value.map_or_else(String::new, |v| v.to_string());
+        writeln!(
+            writer,
+            "file,sample_rate,duration,rt60,edt,drr,c50,c80,spectral_tilt"
+        )?;
+        for row in rows {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{}",
+                csv_field(&row.path.display().to_string()),
+                row.sample_rate,
+                row.duration,
+                cell(row.metrics.rt60),
+                cell(row.metrics.edt),
+                row.metrics.drr,
+                row.metrics.c50,
+                row.metrics.c80,
+                cell(row.metrics.spectral_tilt)
+            )?;
+        }
+    }
+    writer.flush()
+}
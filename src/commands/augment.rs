@@ -0,0 +1,320 @@
+use std::fs;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::Args;
+use ndarray_rand::rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use storir::common::is_supported_sample_rate;
+use storir::dsp::{convolve, mix_at_snr, signed_amplitudes};
+use storir::wav::{read_wav, write_wav};
+use storir::{ImpulseResponseGenerator, ParamRanges};
+
+use super::{
+    build_generator, check_algo, csv_field, find_wav_files, parse_duration_range,
+    parse_level_range, EXIT_INVALID_INPUT, EXIT_IO_ERROR,
+};
+
+#[derive(Args, Debug)]
+pub struct AugmentArgs {
+    /// Folder with dry recordings (wav), searched recursively
+    input: PathBuf,
+    /// Folder to store reverberant recordings and manifest
+    #[arg(short, long, default_value = "augmented")]
+    output: PathBuf,
+    /// Algo
+    #[arg(short, long, default_value = "simple")]
+    algo: String,
+    /// Number of reverberant copies per recording
+    #[arg(short = 'n', long, default_value = "1")]
+    per_file: u32,
+    /// Reverberation time range [ms]
+    #[arg(long, default_value = "200..1000", value_parser = parse_duration_range)]
+    rt60: RangeInclusive<f32>,
+    /// Early decay time range [ms]
+    #[arg(long, default_value = "20..100", value_parser = parse_duration_range)]
+    edt: RangeInclusive<f32>,
+    /// Initial time delay gap range [ms]
+    #[arg(long, default_value = "1..10", value_parser = parse_duration_range)]
+    itdg: RangeInclusive<f32>,
+    /// Early reflections duration range [ms]
+    #[arg(long, default_value = "50..150", value_parser = parse_duration_range)]
+    er_duration: RangeInclusive<f32>,
+    /// Keep reverberation tail instead of trimming to the recording length
+    #[arg(long)]
+    keep_tail: bool,
+    /// Folder with noise recordings (wav) mixed into reverberant copies
+    #[arg(long)]
+    noise: Option<PathBuf>,
+    /// Mix generated white noise into reverberant copies
+    #[arg(long, conflicts_with = "noise")]
+    white_noise: bool,
+    /// Signal to noise ratio range [dB]
+    #[arg(
+        long,
+        default_value = "0..20",
+        value_parser = parse_level_range,
+        allow_hyphen_values = true
+    )]
+    snr: RangeInclusive<f32>,
+    /// Seed of the first reverberant copy, next copies take consecutive
+    /// seeds, random when omitted (seeds are stored in the manifest)
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// Source of noise mixed into reverberant recordings
+enum NoiseSource {
+    None,
+    White,
+    Recordings(Vec<(PathBuf, Vec<f32>, u32)>),
+}
+
+pub fn run(args: AugmentArgs) {
+    // Sampled edt is capped at half of rt60, so rt60 above 0 is all the
+    // generators need beyond the algo, checked before any file is written
+    if let Err(e) = check_algo(&args.algo) {
+        eprintln!("Error: {}", e);
+        process::exit(EXIT_INVALID_INPUT);
+    }
+    if *args.rt60.start() <= 0.0 {
+        eprintln!("Error: rt60 range must be above 0");
+        process::exit(EXIT_INVALID_INPUT);
+    }
+
+    let recordings = match find_wav_files(&args.input) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", args.input.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+    println!(
+        "Found {} recordings in '{}'",
+        recordings.len(),
+        args.input.display()
+    );
+
+    let manifest_path = args.output.join("manifest.csv");
+    let mut manifest = match create_manifest(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Error creating '{}': {}", manifest_path.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+
+    let noise_source = match (&args.noise, args.white_noise) {
+        (Some(folder), _) => NoiseSource::Recordings(load_noise(folder)),
+        (None, true) => NoiseSource::White,
+        (None, false) => NoiseSource::None,
+    };
+
+    let ranges = ParamRanges {
+        rt60: args.rt60.clone(),
+        edt: args.edt.clone(),
+        itdg: args.itdg.clone(),
+        er_duration: args.er_duration.clone(),
+    };
+    // Every copy draws its parameters, impulse, signs and noise from its own
+    // seed, so a manifest row is enough to reproduce it
+    let mut seed = args
+        .seed
+        .unwrap_or_else(|| ndarray_rand::rand::thread_rng().gen());
+
+    for recording in &recordings {
+        let (dry, sample_rate) = match read_wav(recording) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("Skipping '{}': {}", recording.display(), e);
+                continue;
+            }
+        };
+        if !is_supported_sample_rate(sample_rate) {
+            eprintln!(
+                "Skipping '{}': unsupported sample rate {}",
+                recording.display(),
+                sample_rate
+            );
+            continue;
+        }
+
+        // Mirror folder structure of the input
+        let relative = recording.strip_prefix(&args.input).unwrap_or(recording);
+        let stem = relative.with_extension("");
+        for index in 1..=args.per_file {
+            let copy_seed = seed;
+            seed = seed.wrapping_add(1);
+            let mut rng = StdRng::seed_from_u64(copy_seed);
+            let params = ranges.sample(&mut rng);
+            let rir = match build_generator(&args.algo, &params) {
+                Ok(rir) => rir,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(EXIT_INVALID_INPUT);
+                }
+            };
+            // Generators give energies, convolution needs amplitudes
+            let energies = rir.generate_with_rng(sample_rate, &mut rng);
+            let impulse = signed_amplitudes(&energies, &mut rng);
+            let wet = reverberate(&dry, &impulse, args.keep_tail);
+
+            let file_name = format!("{}_rir{}.wav", stem.to_string_lossy(), index);
+            let path = args.output.join(&file_name);
+            if let Some(parent) = path.parent() {
+                if let Err(e) = fs::create_dir_all(parent) {
+                    eprintln!("Error creating folder {} : {}", parent.display(), e);
+                    continue;
+                }
+            }
+            if let Err(e) = write_wav(&wet, sample_rate, &path) {
+                eprintln!("Error: {}", e);
+                continue;
+            }
+
+            let (noisy_name, noise_name, snr) =
+                match pick_noise(&noise_source, wet.len(), sample_rate, &mut rng) {
+                    Some((noise_name, noise)) => {
+                        let snr = rng.gen_range(args.snr.clone());
+                        let noisy = mix_at_snr(&wet, &noise, snr);
+                        let noisy_name = format!(
+                            "{}_rir{}_noisy.wav",
+                            stem.to_string_lossy(),
+                            index
+                        );
+                        let noisy_path = args.output.join(&noisy_name);
+                        if let Err(e) =
+                            write_wav(&normalized(noisy), sample_rate, &noisy_path)
+                        {
+                            eprintln!("Error: {}", e);
+                            continue;
+                        }
+                        println!(
+                            "WAV file '{}' created successfully.",
+                            noisy_path.display()
+                        );
+                        (noisy_name, noise_name, snr.to_string())
+                    }
+                    None => (String::new(), String::new(), String::new()),
+                };
+
+            let row = writeln!(
+                manifest,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                csv_field(&file_name),
+                csv_field(&relative.display().to_string()),
+                index,
+                csv_field(&args.algo),
+                sample_rate,
+                params.rt60,
+                params.edt,
+                params.itdg,
+                params.er_duration,
+                params.drr,
+                copy_seed,
+                csv_field(&noisy_name),
+                csv_field(&noise_name),
+                snr
+            );
+            if let Err(e) = row {
+                eprintln!("Error writing manifest: {}", e);
+                process::exit(EXIT_IO_ERROR);
+            }
+            println!("WAV file '{}' created successfully.", path.display());
+        }
+    }
+    if let Err(e) = manifest.flush() {
+        eprintln!("Error writing manifest: {}", e);
+        process::exit(EXIT_IO_ERROR);
+    }
+    println!("Manifest written to '{}'", manifest_path.display());
+}
+
+/// Convolve dry recording with impulse response, keeping the peak level of
+/// the dry signal so the result does not clip
+fn reverberate(dry: &[f32], impulse: &[f32], keep_tail: bool) -> Vec<f32> {
+    let mut wet = convolve(dry, impulse);
+    if !keep_tail {
+        wet.truncate(dry.len());
+    }
+    let peak = |data: &[f32]| data.iter().fold(0.0_f32, |m, x| m.max(x.abs()));
+    let (dry_peak, wet_peak) = (peak(dry), peak(&wet));
+    if wet_peak > 0.0 {
+        let gain = dry_peak / wet_peak;
+        wet.iter_mut().for_each(|x| *x *= gain);
+    }
+    wet
+}
+
+/// Scale down the signal if it would clip
+fn normalized(mut data: Vec<f32>) -> Vec<f32> {
+    let peak = data.iter().fold(0.0_f32, |m, x| m.max(x.abs()));
+    if peak > 1.0 {
+        data.iter_mut().for_each(|x| *x /= peak);
+    }
+    data
+}
+
+/// Noise segment of given length and its name, random file and offset for
+/// recordings, `None` when noise mixing is off or no noise file matches the
+/// sample rate
+fn pick_noise<R: Rng>(
+    source: &NoiseSource,
+    len: usize,
+    sample_rate: u32,
+    rng: &mut R,
+) -> Option<(String, Vec<f32>)> {
+    match source {
+        NoiseSource::None => None,
+        NoiseSource::White => Some((
+            "white".to_string(),
+            (0..len).map(|_| rng.gen_range(-1.0..1.0)).collect(),
+        )),
+        NoiseSource::Recordings(recordings) => {
+            let matching: Vec<_> = recordings
+                .iter()
+                .filter(|(_, noise, rate)| {
+                    *rate == sample_rate && !noise.is_empty()
+                })
+                .collect();
+            let (path, noise, _) = matching.choose(rng)?;
+            let offset = rng.gen_range(0..noise.len());
+            let segment = noise.iter().cycle().skip(offset).take(len).copied();
+            Some((path.display().to_string(), segment.collect()))
+        }
+    }
+}
+
+/// Load all noise recordings from the folder, unreadable files are skipped
+fn load_noise(folder: &Path) -> Vec<(PathBuf, Vec<f32>, u32)> {
+    let files = match find_wav_files(folder) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", folder.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+    let mut recordings = Vec::new();
+    for path in files {
+        match read_wav(&path) {
+            Ok((noise, sample_rate)) => recordings.push((path, noise, sample_rate)),
+            Err(e) => eprintln!("Skipping '{}': {}", path.display(), e),
+        }
+    }
+    println!("Loaded {} noise recordings", recordings.len());
+    recordings
+}
+
+fn create_manifest(path: &Path) -> io::Result<io::BufWriter<fs::File>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut manifest = io::BufWriter::new(fs::File::create(path)?);
+    writeln!(
+        manifest,
+        "output,source,rir_index,algo,sample_rate,rt60,edt,itdg,er_duration,drr,\
+         seed,noisy_output,noise,snr"
+    )?;
+    Ok(manifest)
+}
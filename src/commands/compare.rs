@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+use std::process;
+
+use clap::Args;
+use storir::analysis::{compare, Metrics};
+use storir::wav::read_wav;
+
+use super::{
+    json_number, metrics_json, ReportFormat, EXIT_INVALID_INPUT, EXIT_IO_ERROR,
+};
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Reference impulse response (wav)
+    reference: PathBuf,
+    /// Impulse response to compare against the reference (wav)
+    candidate: PathBuf,
+    /// Print a table or JSON
+    #[arg(long, value_enum, default_value = "text")]
+    output_format: ReportFormat,
+}
+
+pub fn run(args: CompareArgs) {
+    let (reference, reference_rate) = load(&args.reference);
+    let (candidate, candidate_rate) = load(&args.candidate);
+    if reference_rate != candidate_rate {
+        eprintln!(
+            "Error: sample rates differ ({} vs {})",
+            reference_rate, candidate_rate
+        );
+        process::exit(EXIT_INVALID_INPUT);
+    }
+
+    let comparison = compare(&reference, &candidate, reference_rate);
+    match args.output_format {
+        ReportFormat::Text => {
+            print_table(&comparison.reference, &comparison.candidate);
+            println!("correlation: {:.3}", comparison.correlation);
+        }
+        ReportFormat::Json => println!(
+            "{{\"sample_rate\":{},\"reference\":{},\"candidate\":{},\
+             \"correlation\":{}}}",
+            reference_rate,
+            metrics_json(&comparison.reference),
+            metrics_json(&comparison.candidate),
+            json_number(Some(comparison.correlation))
+        ),
+    }
+}
+
+fn load(path: &PathBuf) -> (Vec<f32>, u32) {
+    match read_wav(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", path.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    }
+}
+
+fn print_table(reference: &Metrics, candidate: &Metrics) {
+    let rows = [
+        ("rt60 [ms]", reference.rt60, candidate.rt60),
+        ("edt [ms]", reference.edt, candidate.edt),
+        ("drr [dB]", Some(reference.drr), Some(candidate.drr)),
+        ("c50 [dB]", Some(reference.c50), Some(candidate.c50)),
+        ("c80 [dB]", Some(reference.c80), Some(candidate.c80)),
+        (
+            "tilt [dB/oct]",
+            reference.spectral_tilt,
+            candidate.spectral_tilt,
+        ),
+    ];
+    println!(
+        "{:<14} {:>10} {:>10} {:>10}",
+        "metric", "reference", "candidate", "diff"
+    );
+    for (name, a, b) in rows {
+        let diff = a.zip(b).map(|(a, b)| b - a);
+        println!(
+            "{:<14} {:>10} {:>10} {:>10}",
+            name,
+            format_value(a),
+            format_value(b),
+            format_value(diff)
+        );
+    }
+}
+
+fn format_value(value: Option<f32>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v))
+}
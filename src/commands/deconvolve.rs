@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+use std::process;
+
+use clap::Args;
+use storir::analysis::deconvolve_sweep;
+use storir::wav::{read_wav, write_wav};
+
+use super::{EXIT_INVALID_INPUT, EXIT_IO_ERROR};
+
+#[derive(Args, Debug)]
+pub struct DeconvolveArgs {
+    /// Recording of the room playing the sweep (wav)
+    recording: PathBuf,
+    /// Sweep that was played (wav)
+    #[arg(long)]
+    sweep: PathBuf,
+    /// Impulse response file (wav)
+    #[arg(short, long, default_value = "measured.wav")]
+    output: PathBuf,
+}
+
+pub fn run(args: DeconvolveArgs) {
+    let read = |path: &PathBuf| match read_wav(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", path.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+    let (recording, sample_rate) = read(&args.recording);
+    let (sweep, sweep_rate) = read(&args.sweep);
+    if sample_rate != sweep_rate {
+        eprintln!(
+            "Error: recording is sampled at {} Hz, sweep at {} Hz",
+            sample_rate, sweep_rate
+        );
+        process::exit(EXIT_INVALID_INPUT);
+    }
+    if sweep.is_empty() || recording.len() < sweep.len() {
+        eprintln!("Error: recording is shorter than the sweep");
+        process::exit(EXIT_INVALID_INPUT);
+    }
+
+    let mut ir = deconvolve_sweep(&recording, &sweep);
+
+    // Level depends on the playback chain, store the response normalized
+    let peak = ir.iter().fold(0.0_f32, |m, x| m.max(x.abs()));
+    if peak > 0.0 {
+        ir.iter_mut().for_each(|x| *x /= peak);
+    }
+    match write_wav(&ir, sample_rate, &args.output) {
+        Ok(()) => println!(
+            "WAV file '{}' created successfully, {:.2} ms long.",
+            args.output.display(),
+            ir.len() as f32 / sample_rate as f32 * 1000.0
+        ),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    }
+}
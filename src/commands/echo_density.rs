@@ -0,0 +1,141 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::Args;
+use storir::analysis::echo_density;
+use storir::wav::read_wav;
+
+use super::{parse_duration, EXIT_IO_ERROR};
+
+/// Most points drawn in the SVG plot, longer profiles are decimated
+const PLOT_POINTS: usize = 2000;
+
+#[derive(Args, Debug)]
+pub struct EchoDensityArgs {
+    /// Impulse response (wav)
+    input: PathBuf,
+    /// Analysis window, like `20` or `20ms` [ms]
+    #[arg(short, long, default_value = "20", value_parser = parse_duration)]
+    window: f32,
+    /// CSV with time [ms] and echo density of every sample
+    #[arg(long, value_name = "FILE")]
+    csv: Option<PathBuf>,
+    /// SVG plot of the profile
+    #[arg(long, value_name = "FILE")]
+    plot: Option<PathBuf>,
+}
+
+pub fn run(args: EchoDensityArgs) {
+    let (ir, sample_rate) = match read_wav(&args.input) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", args.input.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+    let density = echo_density(&ir, sample_rate, args.window);
+    let time = |index: usize| index as f32 / sample_rate as f32 * 1000.0;
+
+    // Transition from isolated reflections to a diffuse tail
+    match density.iter().position(|&d| d >= 1.0) {
+        Some(index) => println!(
+            "Echo density reaches 1 at {:.2} ms ({} ms window)",
+            time(index),
+            args.window
+        ),
+        None => println!(
+            "Echo density stays below 1, peak {:.2} ({} ms window)",
+            density.iter().copied().fold(0.0, f32::max),
+            args.window
+        ),
+    }
+
+    let mut failed = false;
+    if let Some(path) = &args.csv {
+        failed |= report(path, write_csv(path, &density, sample_rate));
+    }
+    if let Some(path) = &args.plot {
+        failed |= report(path, write_plot(path, &density, sample_rate));
+    }
+    if failed {
+        process::exit(EXIT_IO_ERROR);
+    }
+}
+
+/// Print the outcome of writing the file, returns whether it failed
+fn report(path: &Path, result: io::Result<()>) -> bool {
+    match result {
+        Ok(()) => {
+            println!("File '{}' created successfully.", path.display());
+            false
+        }
+        Err(e) => {
+            eprintln!("Error writing '{}': {}", path.display(), e);
+            true
+        }
+    }
+}
+
+fn write_csv(path: &Path, density: &[f32], sample_rate: u32) -> io::Result<()> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    writeln!(writer, "time,echo_density")?;
+    for (index, value) in density.iter().enumerate() {
+        let time = index as f32 / sample_rate as f32 * 1000.0;
+        writeln!(writer, "{},{}", time, value)?;
+    }
+    writer.flush()
+}
+
+/// Line plot with the diffuse level 1 dashed, time [ms] on the x axis
+fn write_plot(path: &Path, density: &[f32], sample_rate: u32) -> io::Result<()> {
+    let (width, height, margin) = (800.0, 300.0, 40.0);
+    let duration = density.len() as f32 / sample_rate as f32 * 1000.0;
+    let top = density.iter().copied().fold(1.2, f32::max);
+    let x = |index: usize| {
+        margin + index as f32 / density.len().max(1) as f32 * (width - 2.0 * margin)
+    };
+    let y = |value: f32| height - margin - value / top * (height - 2.0 * margin);
+
+    let step = density.len().div_ceil(PLOT_POINTS).max(1);
+    let points: Vec<String> = density
+        .iter()
+        .enumerate()
+        .step_by(step)
+        .map(|(index, &value)| format!("{:.1},{:.1}", x(index), y(value)))
+        .collect();
+
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    writeln!(
+        writer,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         font-family=\"sans-serif\" font-size=\"12\">\n\
+         <rect x=\"{m}\" y=\"{m}\" width=\"{pw}\" height=\"{ph}\" fill=\"none\" \
+         stroke=\"black\"/>\n\
+         <line x1=\"{m}\" y1=\"{one:.1}\" x2=\"{right}\" y2=\"{one:.1}\" \
+         stroke=\"gray\" stroke-dasharray=\"4 4\"/>\n\
+         <polyline fill=\"none\" stroke=\"steelblue\" points=\"{points}\"/>\n\
+         <text x=\"{m}\" y=\"{below}\">0</text>\n\
+         <text x=\"{right}\" y=\"{below}\" text-anchor=\"end\">{duration:.0} ms\
+         </text>\n\
+         <text x=\"{left}\" y=\"{one:.1}\" text-anchor=\"end\">1</text>\n\
+         <text x=\"{center}\" y=\"{title}\" text-anchor=\"middle\">\
+         normalized echo density</text>\n\
+         </svg>",
+        w = width,
+        h = height,
+        m = margin,
+        pw = width - 2.0 * margin,
+        ph = height - 2.0 * margin,
+        one = y(1.0),
+        right = width - margin,
+        below = height - margin + 15.0,
+        left = margin - 5.0,
+        center = width / 2.0,
+        title = margin - 10.0,
+        points = points.join(" "),
+        duration = duration
+    )?;
+    writer.flush()
+}
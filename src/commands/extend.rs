@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+use std::process;
+
+use clap::Args;
+use storir::analysis::noise_floor;
+use storir::dsp::extend_tail;
+use storir::rand::{rngs::StdRng, SeedableRng};
+use storir::wav::{read_wav, write_wav};
+
+use super::{parse_level, EXIT_INVALID_INPUT, EXIT_IO_ERROR};
+
+#[derive(Args, Debug)]
+pub struct ExtendArgs {
+    /// Measured impulse response (wav)
+    input: PathBuf,
+    /// Extended impulse response file (wav)
+    #[arg(short, long, default_value = "extended.wav")]
+    output: PathBuf,
+    /// Depth of the synthetic tail below the peak [dB]
+    #[arg(short, long, default_value = "90", value_parser = parse_level)]
+    depth: f32,
+    /// Seed of the synthetic tail, random when omitted
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+pub fn run(args: ExtendArgs) {
+    let (ir, sample_rate) = match read_wav(&args.input) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", args.input.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+    let floor = match noise_floor(&ir, sample_rate) {
+        Some(floor) => floor,
+        None => {
+            eprintln!(
+                "Error: no noise floor found in '{}', nothing to extend",
+                args.input.display()
+            );
+            process::exit(EXIT_INVALID_INPUT);
+        }
+    };
+    println!(
+        "Noise floor at {:.2} dB starts at {:.2} ms, decay {:.2} dB/ms \
+         (rt60 {:.2} ms)",
+        floor.level,
+        floor.start as f32 / sample_rate as f32 * 1000.0,
+        floor.decay_rate,
+        -60.0 / floor.decay_rate
+    );
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let extended = match extend_tail(&ir, &floor, args.depth, sample_rate, &mut rng)
+    {
+        Some(extended) => extended,
+        None => {
+            eprintln!(
+                "Error: '{}' does not decay before its noise floor, nothing \
+                     to extend",
+                args.input.display()
+            );
+            process::exit(EXIT_INVALID_INPUT);
+        }
+    };
+    match write_wav(&extended, sample_rate, &args.output) {
+        Ok(()) => println!(
+            "WAV file '{}' created successfully, {:.2} ms long.",
+            args.output.display(),
+            extended.len() as f32 / sample_rate as f32 * 1000.0
+        ),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    }
+}
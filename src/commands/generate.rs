@@ -0,0 +1,1060 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::builder::TypedValueParser;
+use clap::{Args, ValueEnum};
+use storir::analysis::{clarity, quantization_impact};
+use storir::common::{ms_to_samples, MAX_SAMPLE_RATE, MIN_SAMPLE_RATE};
+use storir::dsp::{apply_gain, clipped_samples, resample};
+use storir::ensemble::{Ensemble, EnsembleMode};
+use storir::mat::{save_mat, MatImpulse};
+use storir::multichannel::generate_multichannel;
+use storir::rand::{rngs::StdRng, Rng, SeedableRng};
+use storir::sparse::SparseIr;
+use storir::verification::{Measurement, Tolerances};
+use storir::wav::{
+    wav_size, write_multichannel_wav_with_metadata, write_wav_with_metadata,
+    Metadata,
+};
+use storir::{ImpulseResponseGenerator, RirParams};
+
+use super::preset::{self, parse_preset_name, Preset, PresetError};
+use super::{
+    build_generator, check_algo, json_number, json_string, parse_duration,
+    parse_level, read_csv_records, Generator, ReportFormat, EXIT_INVALID_INPUT,
+    EXIT_IO_ERROR, EXIT_TARGET_MISSED, EXIT_USAGE,
+};
+
+/// Inter-channel cross-correlation of the tails without `--iacc`
+const DEFAULT_IACC: f32 = 0.3;
+
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// Algo
+    #[arg(short, long, default_value = "simple")]
+    algo: String,
+    /// Sample rate [8000..=192000 Hz]
+    #[arg(
+        short,
+        long,
+        default_value = "44100",
+        value_parser = clap::value_parser!(u32)
+            .range(MIN_SAMPLE_RATE as i64..=MAX_SAMPLE_RATE as i64)
+    )]
+    sample_rate: u32,
+    /// Several sample rates like `16000,48000`, impulse is generated once at
+    /// the highest rate and resampled to the others (overrides sample rate)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u32)
+            .range(MIN_SAMPLE_RATE as i64..=MAX_SAMPLE_RATE as i64)
+    )]
+    sample_rates: Vec<u32>,
+    /// Folder to store wav files
+    #[arg(short, long, default_value = "impulses")]
+    folder: String,
+    /// Number of impulses to generate
+    #[arg(short, long, default_value = "5")]
+    num_impulses: u32,
+    /// Reverberation time, like `800`, `800ms` or `1.2s` [ms]
+    #[arg(long, default_value = "500", value_parser = parse_duration)]
+    rt60: f32,
+    /// Early decay time [ms]
+    #[arg(long, default_value = "50", value_parser = parse_duration)]
+    edt: f32,
+    /// Initial time delay gap [ms]
+    #[arg(long, default_value = "4", value_parser = parse_duration)]
+    itdg: f32,
+    /// Early reflections duration [ms]
+    #[arg(long, default_value = "100", value_parser = parse_duration)]
+    er_duration: f32,
+    /// Direct to reverberant ratio, like `-3` or `-3dB`, random when omitted
+    /// [dB]
+    #[arg(long, value_parser = parse_level, allow_hyphen_values = true)]
+    drr: Option<f32>,
+    /// Output gain, negative values leave headroom for convolution [dB]
+    #[arg(
+        long,
+        default_value = "0",
+        value_parser = parse_level,
+        allow_hyphen_values = true
+    )]
+    gain_db: f32,
+    /// Fail instead of warning when WAV samples clip after the gain
+    #[arg(long)]
+    fail_on_clip: bool,
+    /// Output file format
+    #[arg(long, value_enum, default_value = "wav")]
+    format: OutputFormat,
+    /// Only report quantization SNR and decay range loss at given bit depth,
+    /// no files are written
+    #[arg(
+        long,
+        value_name = "BITS",
+        value_parser = clap::builder::PossibleValuesParser::new(["16", "24"])
+            .map(|s| s.parse::<u32>().unwrap())
+    )]
+    quantization_preview: Option<u32>,
+    /// Seed of the first impulse, next impulses take consecutive seeds,
+    /// random when omitted (seeds are stored in WAV metadata)
+    #[arg(long)]
+    seed: Option<u64>,
+    /// CSV with one impulse per row: rt60, edt, itdg, er_duration columns and
+    /// optional drr, seed, filename (without extension) and algo, replaces
+    /// the parameter options and number of impulses
+    #[arg(long, value_name = "CSV")]
+    from_csv: Option<PathBuf>,
+    /// Only print sample counts, file sizes, memory use and metrics of one
+    /// impulse per parameter set, no files are written
+    #[arg(long)]
+    dry_run: bool,
+    /// Number of channels, stored as `_ch<N>` files unless interleaved
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    channels: u16,
+    /// Inter-channel cross-correlation of the tails, 0.0 to 1.0 [default: 0.3]
+    #[arg(long)]
+    iacc: Option<f32>,
+    /// Store all channels in one interleaved WAV file
+    #[arg(long)]
+    interleaved: bool,
+    /// Take algo and parameters from a saved preset, see `storir presets`
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = parse_preset_name,
+        conflicts_with_all = ["algo", "rt60", "edt", "itdg", "er_duration", "drr", "from_csv"]
+    )]
+    preset: Option<String>,
+    /// Save algo and parameters as a preset once all files are written
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = parse_preset_name,
+        conflicts_with_all = ["from_csv", "dry_run", "quantization_preview"]
+    )]
+    save_preset: Option<String>,
+    /// Number of stochastic realizations combined into every impulse, one
+    /// representative impulse per condition instead of a random draw
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    ensemble: u32,
+    /// How ensemble realizations are combined
+    #[arg(long, value_enum, default_value = "mean-energy")]
+    ensemble_mode: EnsembleArg,
+    /// Also store all impulses with their parameters in one MATLAB MAT-file
+    /// (at the highest sample rate, one column per channel)
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["dry_run", "quantization_preview"]
+    )]
+    mat: Option<PathBuf>,
+    /// Measure every generated impulse and print requested vs achieved
+    /// RT60, EDT, DRR and its peak level
+    #[arg(long, conflicts_with_all = ["dry_run", "quantization_preview"])]
+    summary: bool,
+    /// Print the summary and fail when measured RT60 or EDT deviate from
+    /// the requested values by more than TOLERANCE (relative, 0.2 is 20 %)
+    /// or DRR by more than 3 dB
+    #[arg(
+        long,
+        value_name = "TOLERANCE",
+        conflicts_with_all = ["dry_run", "quantization_preview"]
+    )]
+    fail_on_miss: Option<f32>,
+    /// Report format, `json` prints one object per written file
+    #[arg(long, value_enum, default_value = "text")]
+    output_format: ReportFormat,
+}
+
+/// Single impulse to generate
+struct Job {
+    algo: String,
+    params: RirParams,
+    seed: u64,
+    /// File name without extension
+    name: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    /// 16 bit mono WAV
+    Wav,
+    /// Binary (index, value) pairs of non-zero samples (.srir)
+    Sparse,
+    /// CSV (index, value) pairs of non-zero samples
+    SparseCsv,
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Wav => "wav",
+            OutputFormat::Sparse => "srir",
+            OutputFormat::SparseCsv => "csv",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum EnsembleArg {
+    /// Mean energy of all realizations, dense tail keeping their DRR
+    MeanEnergy,
+    /// Realization with the decay curve closest to the median one
+    MedianEdc,
+}
+
+/// Generator of the job, combining `--ensemble` realizations
+fn job_generator(
+    args: &GenerateArgs,
+    algo: &str,
+    params: &RirParams,
+) -> Ensemble<Generator> {
+    // Jobs are validated up front, errors here would be bugs in that
+    let generator = build_generator(algo, params).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        process::exit(EXIT_INVALID_INPUT);
+    });
+    Ensemble::new(generator, args.ensemble as usize).with_mode(ensemble_mode(args))
+}
+
+fn ensemble_mode(args: &GenerateArgs) -> EnsembleMode {
+    match args.ensemble_mode {
+        EnsembleArg::MeanEnergy => EnsembleMode::MeanEnergy,
+        EnsembleArg::MedianEdc => EnsembleMode::MedianEdc,
+    }
+}
+
+/// Channels of the job's impulse at the generation rate, before gain
+fn generate_job(
+    args: &GenerateArgs,
+    job: &Job,
+    generation_rate: u32,
+) -> Vec<Vec<f32>> {
+    let rir = job_generator(args, &job.algo, &job.params);
+    generate_multichannel(
+        &rir,
+        args.channels as usize,
+        iacc(args),
+        job.params.itdg + job.params.er_duration,
+        generation_rate,
+        &mut StdRng::seed_from_u64(job.seed),
+    )
+}
+
+/// Channels as written at `sample_rate`, resampled with gain applied
+fn channels_at(
+    args: &GenerateArgs,
+    channels: &[Vec<f32>],
+    generation_rate: u32,
+    sample_rate: u32,
+) -> Vec<Vec<f32>> {
+    channels
+        .iter()
+        .map(|channel| {
+            let mut channel = resample(channel, generation_rate, sample_rate);
+            apply_gain(&mut channel, args.gain_db);
+            channel
+        })
+        .collect()
+}
+
+/// Requested inter-channel cross-correlation or the default one
+fn iacc(args: &GenerateArgs) -> f32 {
+    args.iacc.unwrap_or(DEFAULT_IACC)
+}
+
+/// Metadata of the job with every option that shapes its samples
+fn job_metadata(args: &GenerateArgs, job: &Job, generation_rate: u32) -> Metadata {
+    Metadata::new(&job.algo, job.params, Some(job.seed))
+        .with_gain_db(args.gain_db)
+        .with_sample_rate(generation_rate)
+        .with_channels(args.channels, iacc(args))
+        .with_ensemble(args.ensemble, ensemble_mode(args))
+}
+
+/// Store impulse response in the chosen format
+fn save(
+    impulse: &[f32],
+    sample_rate: u32,
+    format: OutputFormat,
+    metadata: &Metadata,
+    path: &Path,
+) -> Result<(), String> {
+    match format {
+        OutputFormat::Wav => {
+            write_wav_with_metadata(impulse, sample_rate, metadata, path)
+                .map_err(|e| e.to_string())
+        }
+        OutputFormat::Sparse => SparseIr::from_dense(impulse, sample_rate)
+            .save(path)
+            .map_err(|e| e.to_string()),
+        OutputFormat::SparseCsv => fs::File::create(path)
+            .and_then(|file| {
+                SparseIr::from_dense(impulse, sample_rate)
+                    .write_csv(std::io::BufWriter::new(file))
+            })
+            .map_err(|e| e.to_string()),
+    }
+}
+
+pub fn run(mut args: GenerateArgs) {
+    if let Some(name) = args.preset.clone() {
+        match preset::load(&name) {
+            Ok(preset) => {
+                args.algo = preset.algo;
+                args.rt60 = preset.rt60;
+                args.edt = preset.edt;
+                args.itdg = preset.itdg;
+                args.er_duration = preset.er_duration;
+                args.drr = preset.drr;
+            }
+            Err(e) => {
+                eprintln!("Error loading preset '{}': {}", name, e);
+                process::exit(match e {
+                    PresetError::Io(_) => EXIT_IO_ERROR,
+                    PresetError::Invalid(_) => EXIT_INVALID_INPUT,
+                });
+            }
+        }
+    }
+
+    let json = args.output_format == ReportFormat::Json;
+    if json && (args.dry_run || args.quantization_preview.is_some()) {
+        eprintln!(
+            "Error: json output is not supported with --dry-run or \
+             --quantization-preview"
+        );
+        process::exit(EXIT_USAGE);
+    }
+    if args.interleaved && !matches!(args.format, OutputFormat::Wav) {
+        eprintln!("Error: interleaved channels are only supported for wav");
+        process::exit(EXIT_USAGE);
+    }
+    if args.iacc.is_some() && args.channels < 2 {
+        eprintln!("Error: --iacc requires --channels greater than 1");
+        process::exit(EXIT_USAGE);
+    }
+    if args.iacc.is_some_and(|iacc| !(0.0..=1.0).contains(&iacc)) {
+        eprintln!("Error: iacc must be between 0.0 and 1.0");
+        process::exit(EXIT_INVALID_INPUT);
+    }
+    if let Err(e) = check_algo(&args.algo) {
+        eprintln!("Error: {}", e);
+        process::exit(EXIT_INVALID_INPUT);
+    }
+    if args.rt60 <= args.edt {
+        eprintln!("Error: rt60 must be greater than edt");
+        process::exit(EXIT_INVALID_INPUT);
+    }
+    if args
+        .fail_on_miss
+        .is_some_and(|tolerance| tolerance.is_nan() || tolerance < 0.0)
+    {
+        eprintln!("Error: miss tolerance must be non-negative");
+        process::exit(EXIT_INVALID_INPUT);
+    }
+
+    let jobs = match &args.from_csv {
+        Some(path) => {
+            let file = match fs::File::open(path) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("Error reading '{}': {}", path.display(), e);
+                    process::exit(EXIT_IO_ERROR);
+                }
+            };
+            match read_jobs(&args, BufReader::new(file)) {
+                Ok(jobs) => jobs,
+                Err(e) => {
+                    eprintln!("Error reading '{}': {}", path.display(), e);
+                    process::exit(EXIT_INVALID_INPUT);
+                }
+            }
+        }
+        None => jobs_from_args(&args),
+    };
+
+    let mut sample_rates = args.sample_rates.clone();
+    sample_rates.sort_unstable();
+    sample_rates.dedup();
+    let multirate = !sample_rates.is_empty();
+    if !multirate {
+        sample_rates.push(args.sample_rate);
+    }
+    let generation_rate = *sample_rates.last().unwrap();
+
+    if args.dry_run {
+        dry_run(&args, &jobs, &sample_rates, generation_rate);
+        return;
+    }
+    if let Some(bits) = args.quantization_preview {
+        preview_quantization(&args, &jobs, &sample_rates, generation_rate, bits);
+        return;
+    }
+
+    // Save to folder
+    if !json {
+        println!("Saving impulses to {}!", args.folder);
+    }
+    if !Path::new(&args.folder).exists() {
+        match fs::create_dir(&args.folder) {
+            Ok(_) if !json => println!(
+                "No such folder found, crate new one '{}' ...",
+                args.folder
+            ),
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("Error creating folder {} : {}", args.folder, err);
+                process::exit(EXIT_IO_ERROR);
+            }
+        }
+    } else if !json {
+        println!("'{}' folder already exists...", args.folder)
+    };
+
+    let mut failed = false;
+    let mut clipped = false;
+    let mut missed = false;
+    let mut mat_impulses = Vec::new();
+    let summary = args.summary || args.fail_on_miss.is_some();
+    if summary && !json {
+        Summary::print_header();
+    }
+
+    for job in jobs {
+        let channels = generate_job(&args, &job, generation_rate);
+        let metadata = job_metadata(&args, &job, generation_rate);
+
+        for &sample_rate in &sample_rates {
+            let rate = if multirate {
+                format!("_sr_{}", sample_rate)
+            } else {
+                String::new()
+            };
+            let channels =
+                channels_at(&args, &channels, generation_rate, sample_rate);
+
+            if args.interleaved {
+                let path = output_path(&args, &format!("{}{}", job.name, rate));
+                let channels: Vec<&[f32]> =
+                    channels.iter().map(Vec::as_slice).collect();
+                if !check_clipping(&args, &channels, &path) {
+                    clipped = true;
+                    continue;
+                }
+                let result = write_multichannel_wav_with_metadata(
+                    &channels,
+                    sample_rate,
+                    &metadata,
+                    &path,
+                );
+                let output = Output {
+                    path: &path,
+                    job: &job,
+                    sample_rate,
+                    channel: None,
+                };
+                failed |= !output
+                    .report(args.output_format, result.map_err(|e| e.to_string()));
+                continue;
+            }
+            for (index, channel) in channels.iter().enumerate() {
+                let suffix = if channels.len() > 1 {
+                    format!("_ch{}", index + 1)
+                } else {
+                    String::new()
+                };
+                let path =
+                    output_path(&args, &format!("{}{}{}", job.name, rate, suffix));
+                if !check_clipping(&args, &[channel], &path) {
+                    clipped = true;
+                    continue;
+                }
+                let result =
+                    save(channel, sample_rate, args.format, &metadata, &path);
+                let output = Output {
+                    path: &path,
+                    job: &job,
+                    sample_rate,
+                    channel: (channels.len() > 1).then_some(index + 1),
+                };
+                failed |= !output.report(args.output_format, result);
+            }
+        }
+
+        // Channels as written at the generation rate, the summary measures
+        // every one of them
+        let channels: Vec<Vec<f32>> = channels
+            .into_iter()
+            .map(|mut channel| {
+                apply_gain(&mut channel, args.gain_db);
+                channel
+            })
+            .collect();
+        if summary {
+            let tolerances = args.fail_on_miss.map(|tolerance| Tolerances {
+                rt60: tolerance,
+                edt: tolerance,
+                ..Tolerances::default()
+            });
+            for (index, channel) in channels.iter().enumerate() {
+                let channel_number = (channels.len() > 1).then_some(index + 1);
+                let summary = Summary::measure(
+                    &job,
+                    channel_number,
+                    channel,
+                    generation_rate,
+                );
+                let passed = tolerances.is_none_or(|t| summary.passed(&t));
+                summary.report(args.output_format, passed);
+                missed |= !passed;
+            }
+        }
+        if args.mat.is_some() {
+            let multichannel = channels.len() > 1;
+            for (index, samples) in channels.into_iter().enumerate() {
+                let name = match multichannel {
+                    true => format!("{}_ch{}", job.name, index + 1),
+                    false => job.name.clone(),
+                };
+                mat_impulses.push(MatImpulse {
+                    name,
+                    algo: job.algo.clone(),
+                    params: job.params,
+                    seed: Some(job.seed),
+                    samples,
+                });
+            }
+        }
+    }
+    if let Some(path) = &args.mat {
+        match save_mat(&mat_impulses, generation_rate, path) {
+            Ok(_) => match args.output_format {
+                ReportFormat::Text => {
+                    println!("MAT-file '{}' created successfully.", path.display())
+                }
+                ReportFormat::Json => println!(
+                    "{{\"path\":{},\"impulses\":{}}}",
+                    json_string(&path.to_string_lossy()),
+                    mat_impulses.len()
+                ),
+            },
+            Err(e) => {
+                eprintln!("Error writing '{}': {}", path.display(), e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(EXIT_IO_ERROR);
+    }
+    if clipped {
+        process::exit(EXIT_INVALID_INPUT);
+    }
+    if missed {
+        eprintln!("Error: generated impulses missed the requested parameters");
+        process::exit(EXIT_TARGET_MISSED);
+    }
+
+    if let Some(name) = &args.save_preset {
+        let preset = Preset {
+            algo: args.algo.clone(),
+            rt60: args.rt60,
+            edt: args.edt,
+            itdg: args.itdg,
+            er_duration: args.er_duration,
+            drr: args.drr,
+        };
+        match preset::save(name, &preset) {
+            Ok(path) if !json => {
+                println!("Preset '{}' saved to '{}'", name, path.display())
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Error saving preset '{}': {}", name, e);
+                process::exit(EXIT_IO_ERROR);
+            }
+        }
+    }
+}
+
+/// Warn about samples clipping in 16 bit WAV files, returns false when the
+/// file should not be written (`--fail-on-clip`)
+fn check_clipping(args: &GenerateArgs, channels: &[&[f32]], path: &Path) -> bool {
+    if !matches!(args.format, OutputFormat::Wav) {
+        return true;
+    }
+    let clipped: usize = channels.iter().map(|c| clipped_samples(c)).sum();
+    if clipped == 0 {
+        return true;
+    }
+    if args.fail_on_clip {
+        eprintln!(
+            "Error: {} samples of '{}' clip, lower --gain-db",
+            clipped,
+            path.display()
+        );
+        return false;
+    }
+    eprintln!(
+        "Warning: {} samples of '{}' clip, lower --gain-db",
+        clipped,
+        path.display()
+    );
+    true
+}
+
+/// Platform independent path of the output file
+fn output_path(args: &GenerateArgs, name: &str) -> PathBuf {
+    let mut path_buf = PathBuf::new();
+    path_buf.push(args.folder.clone());
+    path_buf.push(format!("{}.{}", name, args.format.extension()));
+    path_buf
+}
+
+/// File written for one channel of an impulse at one sample rate
+struct Output<'a> {
+    path: &'a Path,
+    job: &'a Job,
+    sample_rate: u32,
+    /// Channel number (from 1) of separately stored channels
+    channel: Option<usize>,
+}
+
+impl Output<'_> {
+    /// Print the outcome of writing the file, returns whether it succeeded
+    fn report(&self, format: ReportFormat, result: Result<(), String>) -> bool {
+        if let Err(e) = result {
+            eprintln!("Error writing '{}': {}", self.path.display(), e);
+            return false;
+        }
+        match format {
+            ReportFormat::Text => {
+                println!("File '{}' created successfully.", self.path.display())
+            }
+            ReportFormat::Json => println!("{}", self.json()),
+        }
+        true
+    }
+
+    fn json(&self) -> String {
+        let params = &self.job.params;
+        format!(
+            "{{\"path\":{},\"algo\":{},\"seed\":{},\"sample_rate\":{},\
+             \"channel\":{},\"params\":{{\"rt60\":{},\"edt\":{},\"itdg\":{},\
+             \"er_duration\":{},\"drr\":{}}}}}",
+            json_string(&self.path.to_string_lossy()),
+            json_string(&self.job.algo),
+            self.job.seed,
+            self.sample_rate,
+            self.channel
+                .map_or_else(|| "null".to_string(), |c| c.to_string()),
+            json_number(Some(params.rt60)),
+            json_number(Some(params.edt)),
+            json_number(Some(params.itdg)),
+            json_number(Some(params.er_duration)),
+            json_number(Some(params.drr))
+        )
+    }
+}
+
+/// Requested vs measured parameters of one generated impulse
+struct Summary<'a> {
+    job: &'a Job,
+    /// Channel number (from 1) of multichannel impulses
+    channel: Option<usize>,
+    metrics: Measurement,
+    /// Peak level [dBFS]
+    peak: f32,
+}
+
+impl<'a> Summary<'a> {
+    fn measure(
+        job: &'a Job,
+        channel: Option<usize>,
+        impulse: &[f32],
+        sample_rate: u32,
+    ) -> Self {
+        let peak = impulse.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        Self {
+            job,
+            channel,
+            metrics: Measurement::measure(impulse, sample_rate),
+            peak: 20.0 * peak.log10(),
+        }
+    }
+
+    /// Parameters that could not be measured count as missed
+    fn passed(&self, tolerances: &Tolerances) -> bool {
+        self.metrics.within(&self.job.params, tolerances)
+    }
+
+    fn print_header() {
+        println!(
+            "{:<40} {:>17} {:>17} {:>15} {:>11} {:>6}",
+            "impulse", "rt60 [ms]", "edt [ms]", "drr [dB]", "peak [dBFS]", ""
+        );
+    }
+
+    /// Impulse name with the channel suffix of its files
+    fn name(&self) -> String {
+        match self.channel {
+            Some(channel) => format!("{}_ch{}", self.job.name, channel),
+            None => self.job.name.clone(),
+        }
+    }
+
+    fn report(&self, format: ReportFormat, passed: bool) {
+        let params = &self.job.params;
+        match format {
+            ReportFormat::Text => {
+                let cell = |target: f32, achieved: Option<f32>| {
+                    let achieved = achieved
+                        .map_or_else(|| "-".to_string(), |a| format!("{:.1}", a));
+                    format!("{:.1} / {}", target, achieved)
+                };
+                println!(
+                    "{:<40} {:>17} {:>17} {:>15} {:>11.1} {:>6}",
+                    self.name(),
+                    cell(params.rt60, self.metrics.rt60),
+                    cell(params.edt, self.metrics.edt),
+                    cell(params.drr, Some(self.metrics.drr)),
+                    self.peak,
+                    if passed { "" } else { "MISS" }
+                );
+            }
+            ReportFormat::Json => println!(
+                "{{\"impulse\":{},\"channel\":{},\"requested\":{{\"rt60\":{},\"edt\":{},\
+                 \"drr\":{}}},\"achieved\":{{\"rt60\":{},\"edt\":{},\"drr\":{},\
+                 \"peak\":{}}},\"passed\":{}}}",
+                json_string(&self.job.name),
+                self.channel
+                    .map_or_else(|| "null".to_string(), |c| c.to_string()),
+                json_number(Some(params.rt60)),
+                json_number(Some(params.edt)),
+                json_number(Some(params.drr)),
+                json_number(self.metrics.rt60),
+                json_number(self.metrics.edt),
+                json_number(Some(self.metrics.drr)),
+                json_number(Some(self.peak)),
+                passed
+            ),
+        }
+    }
+}
+
+/// Seed of the impulse with given index (from 1), consecutive from `--seed`
+/// or random
+fn impulse_seed(args: &GenerateArgs, index: u32) -> u64 {
+    args.seed.map_or_else(
+        || ndarray_rand::rand::thread_rng().gen(),
+        |seed| seed.wrapping_add(index as u64 - 1),
+    )
+}
+
+fn default_name(algo: &str, params: &RirParams, index: u32) -> String {
+    format!(
+        "{}_rt60_{}_edt_{}_itdg_{}_erd_{}_i{}",
+        algo, params.rt60, params.edt, params.itdg, params.er_duration, index
+    )
+}
+
+/// Impulses described by the command line options, all share the same DRR
+fn jobs_from_args(args: &GenerateArgs) -> Vec<Job> {
+    // With fixed seed DRR is drawn reproducibly too
+    let params = match args.seed {
+        Some(seed) => random_params(args, &mut StdRng::seed_from_u64(seed)),
+        None => random_params(args, &mut ndarray_rand::rand::thread_rng()),
+    };
+    (1..=args.num_impulses)
+        .map(|index| Job {
+            algo: args.algo.clone(),
+            params,
+            seed: impulse_seed(args, index),
+            name: default_name(&args.algo, &params, index),
+        })
+        .collect()
+}
+
+/// Impulses from CSV rows (RFC 4180 quoting), empty optional cells are
+/// treated as missing.
+///
+/// Every row is validated here (times finite and non-negative through
+/// `parse_duration`, rt60 above edt, known algo, filenames without path
+/// separators or `..`), so a bad row fails the run before any file is
+/// written instead of panicking in the generator or writing outside the
+/// output folder.
+fn read_jobs<R: BufRead>(
+    args: &GenerateArgs,
+    reader: R,
+) -> Result<Vec<Job>, String> {
+    let mut records = read_csv_records(reader)?.into_iter();
+    let (_, header) = records.next().ok_or_else(|| "empty file".to_string())?;
+    let columns: Vec<String> = header.iter().map(|c| c.to_lowercase()).collect();
+    let column = |name: &str| columns.iter().position(|c| c == name);
+    let required = |name: &str| {
+        column(name).ok_or_else(|| format!("missing '{}' column", name))
+    };
+    let (rt60, edt, itdg, er_duration) = (
+        required("rt60")?,
+        required("edt")?,
+        required("itdg")?,
+        required("er_duration")?,
+    );
+    let (drr, seed, filename, algo) = (
+        column("drr"),
+        column("seed"),
+        column("filename"),
+        column("algo"),
+    );
+
+    let mut jobs = Vec::new();
+    for (index, (row, cells)) in records.enumerate() {
+        let index = index as u32 + 1;
+        let cell = |column: Option<usize>| {
+            column
+                .and_then(|c| cells.get(c))
+                .map(String::as_str)
+                .filter(|v| !v.is_empty())
+        };
+        let duration = |column: usize, name: &str| -> Result<f32, String> {
+            let value = cell(Some(column))
+                .ok_or_else(|| format!("row {}: missing {}", row, name))?;
+            parse_duration(value)
+                .map_err(|e| format!("row {}: {}: {}", row, name, e))
+        };
+
+        let rt60 = duration(rt60, "rt60")?;
+        let edt = duration(edt, "edt")?;
+        if rt60 <= edt {
+            return Err(format!("row {}: rt60 must be greater than edt", row));
+        }
+        let seed = match cell(seed) {
+            Some(v) => v
+                .parse()
+                .map_err(|e| format!("row {}: invalid seed '{}': {}", row, v, e))?,
+            None => impulse_seed(args, index),
+        };
+        let drr = match cell(drr) {
+            Some(v) => {
+                parse_level(v).map_err(|e| format!("row {}: drr: {}", row, e))?
+            }
+            None => RirParams::random_drr(rt60, &mut StdRng::seed_from_u64(seed)),
+        };
+        let params = RirParams::new(
+            rt60,
+            edt,
+            duration(itdg, "itdg")?,
+            duration(er_duration, "er_duration")?,
+            drr,
+        );
+        let algo = cell(algo).unwrap_or(&args.algo).to_string();
+        check_algo(&algo).map_err(|e| format!("row {}: {}", row, e))?;
+        let extension = format!(".{}", args.format.extension());
+        let name = match cell(filename) {
+            Some(name) if name.contains(['/', '\\']) || name.contains("..") => {
+                return Err(format!(
+                    "row {}: filename '{}' must not contain '/', '\\' or '..'",
+                    row, name
+                ));
+            }
+            Some(name) => name.strip_suffix(&extension).unwrap_or(name).to_string(),
+            None => default_name(&algo, &params, index),
+        };
+        jobs.push(Job {
+            algo,
+            params,
+            seed,
+            name,
+        });
+    }
+    Ok(jobs)
+}
+
+/// Print the plan of the run without writing anything
+fn dry_run(
+    args: &GenerateArgs,
+    jobs: &[Job],
+    sample_rates: &[u32],
+    generation_rate: u32,
+) {
+    let channels = args.channels as usize;
+    let files_per_impulse = if args.interleaved { 1 } else { channels };
+    // Common and independent realizations for several channels
+    let realizations = if channels > 1 { channels + 1 } else { 1 };
+
+    // Distinct parameter sets in order of appearance, with impulse counts
+    let mut sets: Vec<(&Job, usize)> = Vec::new();
+    for job in jobs {
+        match sets
+            .iter_mut()
+            .find(|(set, _)| set.algo == job.algo && set.params == job.params)
+        {
+            Some((_, count)) => *count += 1,
+            None => sets.push((job, 1)),
+        }
+    }
+
+    println!(
+        "{} impulses, {} files in '{}', generated at {} Hz",
+        jobs.len(),
+        jobs.len() * sample_rates.len() * files_per_impulse,
+        args.folder,
+        generation_rate
+    );
+    println!(
+        "{:<9} {:>6} {:>8} {:>8} {:>12} {:>12}",
+        "algo", "count", "rt60", "edt", "sample rate", "samples"
+    );
+    let mut total_size = 0;
+    let mut peak_memory = 0;
+    for &(job, count) in &sets {
+        let generated = ms_to_samples(job.params.rt60, generation_rate).max(2);
+        // Noise, thinning order, ray indices and output of the generator,
+        // then every resampled copy with its encoded file
+        let mut memory = realizations * generated * (4 + 4 + 8 + 4);
+        for &sample_rate in sample_rates {
+            let samples = (generated as f64 * sample_rate as f64
+                / generation_rate as f64)
+                .ceil() as usize;
+            let metadata = job_metadata(args, job, generation_rate);
+            let size = match args.format {
+                OutputFormat::Wav if args.interleaved => {
+                    wav_size(samples, channels, &metadata)
+                }
+                OutputFormat::Wav => channels * wav_size(samples, 1, &metadata),
+                // Upper bound, thinned out samples are not stored
+                OutputFormat::Sparse => channels * (17 + 8 * samples),
+                OutputFormat::SparseCsv => channels * (40 + 20 * samples),
+            };
+            total_size += count * size;
+            memory += channels * samples * 4 + size;
+            println!(
+                "{:<9} {:>6} {:>8} {:>8} {:>12} {:>12}",
+                job.algo,
+                count,
+                job.params.rt60,
+                job.params.edt,
+                sample_rate,
+                samples
+            );
+        }
+        peak_memory = peak_memory.max(memory);
+    }
+    let bound = match args.format {
+        OutputFormat::Wav => "",
+        _ => "up to ",
+    };
+    println!("Total size: {}{:.2} MB", bound, total_size as f64 / 1e6);
+    println!(
+        "Peak memory per impulse: ~{:.2} MB",
+        peak_memory as f64 / 1e6
+    );
+
+    println!();
+    println!("Predicted metrics (one impulse per parameter set):");
+    println!(
+        "{:<9} {:>8} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "algo",
+        "rt60",
+        "edt",
+        "drr",
+        "rt60 [ms]",
+        "edt [ms]",
+        "drr [dB]",
+        "c50 [dB]"
+    );
+    let format_value = |value: Option<f32>| {
+        value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v))
+    };
+    for &(job, _) in &sets {
+        let impulse = job_generator(args, &job.algo, &job.params)
+            .generate_with_rng(
+                generation_rate,
+                &mut StdRng::seed_from_u64(job.seed),
+            );
+        let metrics = Measurement::measure(&impulse, generation_rate);
+        // Samples are energies, clarity is measured on amplitudes
+        let amplitudes: Vec<f32> = impulse.iter().map(|x| x.abs().sqrt()).collect();
+        println!(
+            "{:<9} {:>8} {:>8} {:>8.2} {:>10} {:>10} {:>10} {:>10}",
+            job.algo,
+            job.params.rt60,
+            job.params.edt,
+            job.params.drr,
+            format_value(metrics.rt60),
+            format_value(metrics.edt),
+            format_value(Some(metrics.drr)),
+            format_value(Some(clarity(&amplitudes, generation_rate, 50.0)))
+        );
+    }
+}
+
+/// Parameters from the command line with random DRR
+fn random_params<R: Rng>(args: &GenerateArgs, rng: &mut R) -> RirParams {
+    RirParams::new(
+        args.rt60,
+        args.edt,
+        args.itdg,
+        args.er_duration,
+        args.drr
+            .unwrap_or_else(|| RirParams::random_drr(args.rt60, rng)),
+    )
+}
+
+/// Quantization impact on every file `generate` would write, the same
+/// seeds, channels, sample rates and gain
+fn preview_quantization(
+    args: &GenerateArgs,
+    jobs: &[Job],
+    sample_rates: &[u32],
+    generation_rate: u32,
+    bits: u32,
+) {
+    println!(
+        "{:<40} {:>10} {:>16} {:>14}",
+        "impulse", "snr [dB]", "decay range [dB]", "loss [dB]"
+    );
+    let mut worst_loss = 0.0_f32;
+    for job in jobs {
+        let channels = generate_job(args, job, generation_rate);
+        for &sample_rate in sample_rates {
+            let rate = if !args.sample_rates.is_empty() {
+                format!("_sr_{}", sample_rate)
+            } else {
+                String::new()
+            };
+            let channels =
+                channels_at(args, &channels, generation_rate, sample_rate);
+            for (index, channel) in channels.iter().enumerate() {
+                let suffix = if channels.len() > 1 {
+                    format!("_ch{}", index + 1)
+                } else {
+                    String::new()
+                };
+                let impact = match quantization_impact(channel, bits) {
+                    Ok(impact) => impact,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        process::exit(EXIT_INVALID_INPUT);
+                    }
+                };
+                worst_loss = worst_loss.max(impact.decay_range_loss);
+                println!(
+                    "{:<40} {:>10.2} {:>16.2} {:>14.2}",
+                    format!("{}{}{}", job.name, rate, suffix),
+                    impact.snr,
+                    impact.decay_range,
+                    impact.decay_range_loss
+                );
+            }
+        }
+    }
+    println!(
+        "Worst decay range loss at {} bit: {:.2} dB",
+        bits, worst_loss
+    );
+}
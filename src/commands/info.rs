@@ -0,0 +1,150 @@
+use std::path::PathBuf;
+use std::process;
+
+use clap::Args;
+use storir::analysis::Metrics;
+use storir::wav::{read_metadata, read_wav, Metadata};
+
+use super::{json_number, json_string, metrics_json, ReportFormat, EXIT_IO_ERROR};
+
+#[derive(Args, Debug)]
+pub struct InfoArgs {
+    /// Impulse response (wav)
+    input: PathBuf,
+    /// Print a table or JSON
+    #[arg(long, value_enum, default_value = "text")]
+    output_format: ReportFormat,
+}
+
+pub fn run(args: InfoArgs) {
+    let (impulse, sample_rate) = match read_wav(&args.input) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", args.input.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+    // Files without embedded parameters are still described by analysis
+    let metadata = read_metadata(&args.input).ok().flatten();
+    let metrics = Metrics::measure(&impulse, sample_rate);
+    let duration = impulse.len() as f32 / sample_rate as f32 * 1000.0;
+
+    match args.output_format {
+        ReportFormat::Text => {
+            print_text(&args, sample_rate, duration, metadata.as_ref(), &metrics)
+        }
+        ReportFormat::Json => {
+            print_json(&args, sample_rate, duration, metadata.as_ref(), &metrics)
+        }
+    }
+}
+
+fn print_text(
+    args: &InfoArgs,
+    sample_rate: u32,
+    duration: f32,
+    metadata: Option<&Metadata>,
+    metrics: &Metrics,
+) {
+    println!("file: {}", args.input.display());
+    println!("sample rate: {} Hz", sample_rate);
+    println!("duration: {:.2} ms", duration);
+
+    match metadata {
+        Some(metadata) => {
+            let params = &metadata.params;
+            println!();
+            println!("generated by storir {}", metadata.version);
+            println!("{:<16} {:>10}", "algo", metadata.algo);
+            if let Some(seed) = metadata.seed {
+                println!("{:<16} {:>10}", "seed", seed);
+            }
+            println!("{:<16} {:>10.2}", "rt60 [ms]", params.rt60);
+            println!("{:<16} {:>10.2}", "edt [ms]", params.edt);
+            println!("{:<16} {:>10.2}", "itdg [ms]", params.itdg);
+            println!("{:<16} {:>10.2}", "er_duration [ms]", params.er_duration);
+            println!("{:<16} {:>10.2}", "drr [dB]", params.drr);
+            println!("{:<16} {:>10.2}", "gain [dB]", metadata.gain_db);
+            if let Some(sample_rate) = metadata.sample_rate {
+                println!("{:<16} {:>10}", "generated [Hz]", sample_rate);
+            }
+            println!("{:<16} {:>10}", "channels", metadata.channels);
+            if let Some(iacc) = metadata.iacc {
+                println!("{:<16} {:>10.2}", "iacc", iacc);
+            }
+            println!("{:<16} {:>10}", "ensemble", metadata.ensemble);
+            if let Some(mode) = metadata.ensemble_mode {
+                println!("{:<16} {:>10}", "ensemble mode", mode.name());
+            }
+        }
+        None => {
+            println!();
+            println!("no embedded parameters, measured metrics only");
+        }
+    }
+
+    println!();
+    println!("measured:");
+    let rows = [
+        ("rt60 [ms]", metrics.rt60),
+        ("edt [ms]", metrics.edt),
+        ("drr [dB]", Some(metrics.drr)),
+        ("c50 [dB]", Some(metrics.c50)),
+        ("c80 [dB]", Some(metrics.c80)),
+        ("tilt [dB/oct]", metrics.spectral_tilt),
+    ];
+    for (name, value) in rows {
+        let value = value.map_or_else(|| "-".to_string(), |v| format!("{:.2}", v));
+        println!("{:<16} {:>10}", name, value);
+    }
+}
+
+fn print_json(
+    args: &InfoArgs,
+    sample_rate: u32,
+    duration: f32,
+    metadata: Option<&Metadata>,
+    metrics: &Metrics,
+) {
+    let parameters = metadata.map_or_else(
+        || "null".to_string(),
+        |metadata| {
+            let params = &metadata.params;
+            format!(
+                "{{\"algo\":{},\"version\":{},\"seed\":{},\"rt60\":{},\
+                 \"edt\":{},\"itdg\":{},\"er_duration\":{},\"drr\":{},\
+                 \"gain_db\":{},\"generation_rate\":{},\"channels\":{},\
+                 \"iacc\":{},\"ensemble\":{},\"ensemble_mode\":{}}}",
+                json_string(&metadata.algo),
+                json_string(&metadata.version),
+                metadata
+                    .seed
+                    .map_or_else(|| "null".to_string(), |s| s.to_string()),
+                json_number(Some(params.rt60)),
+                json_number(Some(params.edt)),
+                json_number(Some(params.itdg)),
+                json_number(Some(params.er_duration)),
+                json_number(Some(params.drr)),
+                json_number(Some(metadata.gain_db)),
+                metadata
+                    .sample_rate
+                    .map_or_else(|| "null".to_string(), |r| r.to_string()),
+                metadata.channels,
+                json_number(metadata.iacc),
+                metadata.ensemble,
+                metadata
+                    .ensemble_mode
+                    .map_or_else(|| "null".to_string(), |m| json_string(m.name()))
+            )
+        },
+    );
+    println!(
+        "{{\"file\":{},\"sample_rate\":{},\"duration\":{},\"parameters\":{},\
+         \"measured\":{}}}",
+        json_string(&args.input.display().to_string()),
+        sample_rate,
+        json_number(Some(duration)),
+        parameters,
+        metrics_json(metrics)
+    );
+}
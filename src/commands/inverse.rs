@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use std::process;
+
+use clap::Args;
+use storir::common::ms_to_samples;
+use storir::dsp::inverse_filter;
+use storir::wav::{read_wav, write_wav};
+
+use super::{parse_duration, EXIT_IO_ERROR};
+
+#[derive(Args, Debug)]
+pub struct InverseArgs {
+    /// Impulse response to invert (wav)
+    input: PathBuf,
+    /// Inverse filter file (wav)
+    #[arg(short, long, default_value = "inverse.wav")]
+    output: PathBuf,
+    /// Inverse filter length [ms], twice the impulse response by default
+    #[arg(short, long, value_parser = parse_duration)]
+    length: Option<f32>,
+    /// Regularization, relative to the peak power of the impulse response
+    #[arg(short, long, default_value = "0.001")]
+    regularization: f32,
+}
+
+pub fn run(args: InverseArgs) {
+    let (ir, sample_rate) = match read_wav(&args.input) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", args.input.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+    let length = args
+        .length
+        .map_or(2 * ir.len(), |ms| ms_to_samples(ms, sample_rate));
+
+    let mut inverse = inverse_filter(&ir, length, args.regularization);
+
+    // Inverse filters easily exceed full scale, store them normalized
+    let peak = inverse.iter().fold(0.0_f32, |m, x| m.max(x.abs()));
+    if peak > 0.0 {
+        inverse.iter_mut().for_each(|x| *x /= peak);
+    }
+    match write_wav(&inverse, sample_rate, &args.output) {
+        Ok(()) => {
+            println!(
+                "WAV file '{}' created successfully, modeling delay {} samples, \
+                 apply {:.2} dB gain to restore the level.",
+                args.output.display(),
+                length / 2,
+                20.0 * peak.log10()
+            )
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::fs;
+use std::io::{self, BufRead};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use storir::analysis::Metrics;
+use storir::common::is_valid_duration;
+use storir::rand::RngCore;
+use storir::{
+    ImpulseResponseGenerator, ImpulseResponseImproved, ImpulseResponseSimple,
+    RirParams,
+};
+
+pub mod analyze;
+pub mod augment;
+pub mod compare;
+pub mod deconvolve;
+pub mod echo_density;
+pub mod extend;
+pub mod generate;
+pub mod info;
+pub mod inverse;
+pub mod preset;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod sweep;
+
+/// Exit code when files can't be read or written
+pub const EXIT_IO_ERROR: i32 = 1;
+/// Exit code of invalid command line usage, same as clap uses
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code when readable input fails validation, e.g. invalid parameters,
+/// malformed CSV rows or mismatched sample rates
+pub const EXIT_INVALID_INPUT: i32 = 3;
+/// Exit code when generated impulses miss the requested parameters
+/// (`generate --fail-on-miss`)
+pub const EXIT_TARGET_MISSED: i32 = 4;
+
+/// How results are printed on stdout
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Human readable text
+    Text,
+    /// JSON, one document per result
+    Json,
+}
+
+/// Generator picked by algo name on the command line
+pub enum Generator {
+    Simple(ImpulseResponseSimple),
+    Improved(ImpulseResponseImproved),
+}
+
+impl ImpulseResponseGenerator for Generator {
+    fn generate_with_rng(
+        &self,
+        sample_rate: u32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<f32> {
+        match self {
+            Generator::Simple(rir) => rir.generate_with_rng(sample_rate, rng),
+            Generator::Improved(rir) => rir.generate_with_rng(sample_rate, rng),
+        }
+    }
+}
+
+/// Errors on algo names other than the ones `build_generator` knows
+pub fn check_algo(algo: &str) -> Result<(), String> {
+    match algo {
+        "simple" | "improved" => Ok(()),
+        _ => Err(format!(
+            "algo should be in [simple, improved], got '{}'",
+            algo
+        )),
+    }
+}
+
+/// Generator for the algo name given on the command line, errors instead of
+/// panicking on unknown algos and times the generators reject
+pub fn build_generator(
+    algo: &str,
+    params: &RirParams,
+) -> Result<Generator, String> {
+    check_algo(algo)?;
+    let times = [params.rt60, params.edt, params.itdg, params.er_duration];
+    if !times.into_iter().all(is_valid_duration) {
+        return Err("times should be finite and non-negative".to_string());
+    }
+    if params.rt60 <= params.edt {
+        return Err("rt60 must be greater than edt".to_string());
+    }
+    Ok(match algo {
+        "simple" => Generator::Simple(ImpulseResponseSimple::new(
+            params.rt60,
+            params.edt,
+            params.itdg,
+            params.er_duration,
+            params.drr,
+        )),
+        // Only improved is left after check_algo
+        _ => Generator::Improved(ImpulseResponseImproved::new(
+            params.rt60,
+            params.edt,
+            params.itdg,
+            params.er_duration,
+            params.drr,
+        )),
+    })
+}
+
+/// All wav files in the folder and its subfolders, sorted by path
+pub fn find_wav_files(folder: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(find_wav_files(&path)?);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"))
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Parse duration to [ms] like `800`, `800ms`, `2.5ms`, `1.2s` or `500us`,
+/// plain numbers are milliseconds, negative durations are rejected
+pub fn parse_duration(s: &str) -> Result<f32, String> {
+    let lower = s.trim().to_lowercase();
+    let (number, scale) = if let Some(v) = lower.strip_suffix("ms") {
+        (v, 1.0)
+    } else if let Some(v) = lower.strip_suffix("us") {
+        (v, 1e-3)
+    } else if let Some(v) = lower.strip_suffix('s') {
+        (v, 1e3)
+    } else {
+        (lower.as_str(), 1.0)
+    };
+    let duration = parse_number(number, s, "duration", "s, ms or us")? * scale;
+    if duration < 0.0 {
+        return Err(format!("negative duration '{}'", s.trim()));
+    }
+    Ok(duration)
+}
+
+/// Parse level to [dB] like `-3` or `-3dB`
+pub fn parse_level(s: &str) -> Result<f32, String> {
+    let lower = s.trim().to_lowercase();
+    let number = lower.strip_suffix("db").unwrap_or(&lower);
+    parse_number(number, s, "level", "dB")
+}
+
+/// Parse range of durations [ms] like `300..800` or `0.3s..1.2s`, a single
+/// value is a fixed range
+pub fn parse_duration_range(s: &str) -> Result<RangeInclusive<f32>, String> {
+    parse_range(s, parse_duration)
+}
+
+/// Parse range of levels [dB] like `0..20` or `-10dB..-3dB`, a single value
+/// is a fixed range
+pub fn parse_level_range(s: &str) -> Result<RangeInclusive<f32>, String> {
+    parse_range(s, parse_level)
+}
+
+fn parse_range(
+    s: &str,
+    parse: fn(&str) -> Result<f32, String>,
+) -> Result<RangeInclusive<f32>, String> {
+    let (low, high) = match s.split_once("..") {
+        Some((low, high)) => (parse(low)?, parse(high)?),
+        None => (parse(s)?, parse(s)?),
+    };
+    if low > high {
+        return Err(format!("empty range '{}'", s));
+    }
+    Ok(low..=high)
+}
+
+fn parse_number(
+    number: &str,
+    original: &str,
+    kind: &str,
+    units: &str,
+) -> Result<f32, String> {
+    number
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .ok_or_else(|| {
+            format!(
+                "invalid {} '{}', expected a number with optional unit {}",
+                kind,
+                original.trim(),
+                units
+            )
+        })
+}
+
+/// JSON number, missing and non-finite values become `null`
+pub fn json_number(value: Option<f32>) -> String {
+    value
+        .filter(|v| v.is_finite())
+        .map_or_else(|| "null".to_string(), |v| v.to_string())
+}
+
+/// JSON string literal with escaped quotes, backslashes and control characters
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                escaped.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// CSV cell, quoted (with quotes doubled) when it contains a comma, quote or
+/// line break
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// CSV records (RFC 4180) with the line number each starts on, quoted cells
+/// may hold commas, doubled quotes and line breaks, unquoted cells are
+/// trimmed and blank lines skipped
+pub fn read_csv_records<R: BufRead>(
+    reader: R,
+) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut records = Vec::new();
+    let mut lines = reader.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let mut line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let start = index + 1;
+        let mut cells = Vec::new();
+        let mut cell = String::new();
+        let mut quoted = false;
+        let mut was_quoted = false;
+        loop {
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match (quoted, c) {
+                    (true, '"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        cell.push('"');
+                    }
+                    (true, '"') => quoted = false,
+                    (true, c) => cell.push(c),
+                    (false, '"') if cell.trim().is_empty() && !was_quoted => {
+                        cell.clear();
+                        quoted = true;
+                        was_quoted = true;
+                    }
+                    (false, ',') => {
+                        cells.push(finish_cell(&mut cell, was_quoted));
+                        was_quoted = false;
+                    }
+                    (false, c) if was_quoted && !c.is_whitespace() => {
+                        return Err(format!(
+                            "line {}: unexpected '{}' after quoted cell",
+                            start, c
+                        ));
+                    }
+                    (false, c) => cell.push(c),
+                }
+            }
+            if !quoted {
+                break;
+            }
+            // Line break inside a quoted cell
+            cell.push('\n');
+            line = match lines.next() {
+                Some((_, line)) => line.map_err(|e| e.to_string())?,
+                None => return Err(format!("line {}: unterminated quote", start)),
+            };
+        }
+        cells.push(finish_cell(&mut cell, was_quoted));
+        records.push((start, cells));
+    }
+    Ok(records)
+}
+
+/// Cell text, unquoted cells trimmed
+fn finish_cell(cell: &mut String, quoted: bool) -> String {
+    let text = std::mem::take(cell);
+    match quoted {
+        true => text,
+        false => text.trim().to_string(),
+    }
+}
+
+/// Measured metrics as JSON object
+pub fn metrics_json(metrics: &Metrics) -> String {
+    format!(
+        "{{\"rt60\":{},\"edt\":{},\"drr\":{},\"c50\":{},\"c80\":{},\
+         \"spectral_tilt\":{}}}",
+        json_number(metrics.rt60),
+        json_number(metrics.edt),
+        json_number(Some(metrics.drr)),
+        json_number(Some(metrics.c50)),
+        json_number(Some(metrics.c80)),
+        json_number(metrics.spectral_tilt)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_round_trip() {
+        let values = ["plain", "a,b", "say \"hi\"", "two\nlines", ""];
+        let line: Vec<String> = values.iter().map(|v| csv_field(v)).collect();
+        let text = format!("name\n\n {} \n", line.join(","));
+        let records = read_csv_records(text.as_bytes()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].0, 3);
+        assert_eq!(records[1].1, values);
+
+        assert!(read_csv_records("\"open,1".as_bytes()).is_err());
+        assert!(read_csv_records("\"a\"b,1".as_bytes()).is_err());
+    }
+}
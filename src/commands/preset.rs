@@ -0,0 +1,260 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+
+use clap::Args;
+use storir::common::is_valid_duration;
+
+use super::{json_number, json_string, ReportFormat, EXIT_IO_ERROR};
+
+/// Named parameter set stored as `<name>.toml` in the presets folder.
+///
+/// Flat TOML with `algo` as string and times [ms] and drr [dB] as numbers,
+/// presets without drr draw it randomly like the command line does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub algo: String,
+    pub rt60: f32,
+    pub edt: f32,
+    pub itdg: f32,
+    pub er_duration: f32,
+    pub drr: Option<f32>,
+}
+
+impl Preset {
+    fn to_toml(&self) -> String {
+        let mut text = format!(
+            "algo = {}\nrt60 = {}\nedt = {}\nitdg = {}\ner_duration = {}\n",
+            json_string(&self.algo),
+            self.rt60,
+            self.edt,
+            self.itdg,
+            self.er_duration
+        );
+        if let Some(drr) = self.drr {
+            text.push_str(&format!("drr = {}\n", drr));
+        }
+        text
+    }
+
+    fn from_toml(text: &str) -> Result<Self, String> {
+        let (mut algo, mut drr) = (None, None);
+        let (mut rt60, mut edt, mut itdg, mut er_duration) =
+            (None, None, None, None);
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                format!("line {}: expected key = value", number + 1)
+            })?;
+            let (key, value) = (key.trim(), value.trim());
+            let parse = || {
+                value.parse::<f32>().map_err(|e| {
+                    format!(
+                        "line {}: invalid {} '{}': {}",
+                        number + 1,
+                        key,
+                        value,
+                        e
+                    )
+                })
+            };
+            match key {
+                "algo" => {
+                    algo = Some(
+                        value
+                            .strip_prefix('"')
+                            .and_then(|v| v.strip_suffix('"'))
+                            .ok_or_else(|| {
+                                format!(
+                                    "line {}: algo should be a string",
+                                    number + 1
+                                )
+                            })?
+                            .to_string(),
+                    )
+                }
+                "rt60" => rt60 = Some(parse()?),
+                "edt" => edt = Some(parse()?),
+                "itdg" => itdg = Some(parse()?),
+                "er_duration" => er_duration = Some(parse()?),
+                "drr" => drr = Some(parse()?),
+                _ => {
+                    return Err(format!(
+                        "line {}: unknown key '{}'",
+                        number + 1,
+                        key
+                    ))
+                }
+            }
+        }
+        let required = |value: Option<f32>, name: &str| {
+            value.ok_or(format!("missing {}", name))
+        };
+        let preset = Self {
+            algo: algo.unwrap_or_else(|| "simple".to_string()),
+            rt60: required(rt60, "rt60")?,
+            edt: required(edt, "edt")?,
+            itdg: required(itdg, "itdg")?,
+            er_duration: required(er_duration, "er_duration")?,
+            drr,
+        };
+        if !matches!(preset.algo.as_str(), "simple" | "improved") {
+            return Err("algo should be in [simple, improved]".to_string());
+        }
+        let times = [preset.rt60, preset.edt, preset.itdg, preset.er_duration];
+        if !times.into_iter().all(is_valid_duration) {
+            return Err("times should be finite and non-negative".to_string());
+        }
+        if preset.rt60 <= preset.edt {
+            return Err("rt60 must be greater than edt".to_string());
+        }
+        Ok(preset)
+    }
+
+    fn json(&self, name: &str) -> String {
+        format!(
+            "{{\"name\":{},\"algo\":{},\"rt60\":{},\"edt\":{},\"itdg\":{},\
+             \"er_duration\":{},\"drr\":{}}}",
+            json_string(name),
+            json_string(&self.algo),
+            json_number(Some(self.rt60)),
+            json_number(Some(self.edt)),
+            json_number(Some(self.itdg)),
+            json_number(Some(self.er_duration)),
+            json_number(self.drr)
+        )
+    }
+}
+
+/// Why a preset could not be loaded
+pub enum PresetError {
+    Io(io::Error),
+    Invalid(String),
+}
+
+impl std::fmt::Display for PresetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PresetError::Io(e) => write!(f, "{}", e),
+            PresetError::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Presets folder, `$XDG_CONFIG_HOME/storir/presets` or
+/// `~/.config/storir/presets`
+pub fn presets_dir() -> io::Result<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+        })
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no HOME or XDG_CONFIG_HOME")
+        })?;
+    Ok(config.join("storir").join("presets"))
+}
+
+/// Preset names end up in file names, letters, digits, `-` and `_` only
+pub fn parse_preset_name(name: &str) -> Result<String, String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(format!(
+            "invalid preset name '{}', use letters, digits, '-' and '_'",
+            name
+        ))
+    }
+}
+
+pub fn load(name: &str) -> Result<Preset, PresetError> {
+    let path = presets_dir()
+        .map_err(PresetError::Io)?
+        .join(format!("{}.toml", name));
+    let text = fs::read_to_string(path).map_err(PresetError::Io)?;
+    Preset::from_toml(&text).map_err(PresetError::Invalid)
+}
+
+/// Store the preset, replacing the one with the same name
+pub fn save(name: &str, preset: &Preset) -> io::Result<PathBuf> {
+    let dir = presets_dir()?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.toml", name));
+    fs::write(&path, preset.to_toml())?;
+    Ok(path)
+}
+
+#[derive(Args, Debug)]
+pub struct PresetsArgs {
+    /// Print a table or JSON (one object per preset)
+    #[arg(long, value_enum, default_value = "text")]
+    output_format: ReportFormat,
+}
+
+/// List saved presets, sorted by name
+pub fn run(args: PresetsArgs) {
+    let dir = match presets_dir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+    let mut names: Vec<String> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+            .collect(),
+        // No presets saved yet
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => {
+            eprintln!("Error reading '{}': {}", dir.display(), e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+    names.sort();
+
+    if args.output_format == ReportFormat::Text {
+        println!("Presets in '{}'", dir.display());
+        println!(
+            "{:<20} {:<9} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            "name", "algo", "rt60", "edt", "itdg", "erd", "drr"
+        );
+    }
+    for name in names {
+        let preset = match load(&name) {
+            Ok(preset) => preset,
+            Err(e) => {
+                eprintln!("Skipping '{}': {}", name, e);
+                continue;
+            }
+        };
+        match args.output_format {
+            ReportFormat::Text => println!(
+                "{:<20} {:<9} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                name,
+                preset.algo,
+                preset.rt60,
+                preset.edt,
+                preset.itdg,
+                preset.er_duration,
+                preset
+                    .drr
+                    .map_or_else(|| "random".to_string(), |drr| drr.to_string())
+            ),
+            ReportFormat::Json => println!("{}", preset.json(&name)),
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::process;
+
+use clap::Args;
+use storir::analysis::Metrics;
+use storir::common::{
+    is_supported_sample_rate, is_valid_duration, MAX_SAMPLE_RATE, MIN_SAMPLE_RATE,
+};
+use storir::wav::{read_wav_from, write_wav_to};
+use storir::{ImpulseResponseGenerator, RirParams};
+use tiny_http::{Header, Method, Response, Server};
+
+use super::{build_generator, json_number};
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+    /// Port to listen on
+    #[arg(short, long, default_value = "8080")]
+    port: u16,
+}
+
+type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+/// Longest time parameter accepted by /generate [ms], bounds the memory a
+/// single request can allocate
+const MAX_TIME: f32 = 10_000.0;
+
+/// Largest WAV body accepted by /analyze [bytes], about a minute of
+/// 32 bit float audio at 96 kHz
+const MAX_BODY: u64 = 32 * 1024 * 1024;
+
+/// Serve impulse responses over HTTP.
+///
+/// GET /generate?rt60=500&edt=50&itdg=4&er_duration=100&drr=-3&sr=44100
+///     &algo=simple&format=wav  -> WAV body (`format=f32` for raw little
+///     endian floats), omitted parameters take CLI defaults, omitted drr is
+///     random the same way as in generate, times outside [0, MAX_TIME] ms
+///     and non-finite values are rejected with 400
+/// POST /analyze with WAV body -> JSON with measured metrics, bodies over
+///     MAX_BODY bytes are rejected with 413, empty or undecodable audio
+///     with 400
+///
+/// Query values are percent-decoded.
+pub fn run(args: ServeArgs) {
+    let address = format!("{}:{}", args.host, args.port);
+    let server = match Server::http(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Error starting server on {}: {}", address, e);
+            process::exit(1);
+        }
+    };
+    println!("Listening on http://{}", address);
+
+    for mut request in server.incoming_requests() {
+        let (path, query) = match request.url().split_once('?') {
+            Some((path, query)) => (path.to_string(), query.to_string()),
+            None => (request.url().to_string(), String::new()),
+        };
+        let response = match (request.method(), path.as_str()) {
+            (Method::Get, "/generate") => generate(&parse_query(&query)),
+            (Method::Post, "/analyze") => analyze(request.as_reader()),
+            _ => Err((404, "Not found".to_string())),
+        };
+        let response = response.unwrap_or_else(|(code, message)| {
+            Response::from_string(message).with_status_code(code)
+        });
+        if let Err(e) = request.respond(response) {
+            eprintln!("Error: {}", e);
+        }
+    }
+}
+
+fn generate(
+    query: &HashMap<String, String>,
+) -> Result<HttpResponse, (u16, String)> {
+    let get = |name: &str, default: f32| -> Result<f32, (u16, String)> {
+        query.get(name).map_or(Ok(default), |v| {
+            v.parse::<f32>()
+                .map_err(|e| e.to_string())
+                .and_then(|value| match value.is_finite() {
+                    true => Ok(value),
+                    false => Err("not a finite number".to_string()),
+                })
+                .map_err(|e| (400, format!("Invalid {} '{}': {}", name, v, e)))
+        })
+    };
+    // Generators panic on invalid times and allocate rt60 worth of samples
+    let time = |name: &str, default: f32| -> Result<f32, (u16, String)> {
+        let value = get(name, default)?;
+        if !is_valid_duration(value) || value > MAX_TIME {
+            return Err((
+                400,
+                format!("{} must be in range [0, {}] ms", name, MAX_TIME),
+            ));
+        }
+        Ok(value)
+    };
+    let rt60 = time("rt60", 500.0)?;
+    let edt = time("edt", 50.0)?;
+    let drr = match query.get("drr") {
+        Some(_) => get("drr", 0.0)?,
+        None => RirParams::random_drr(rt60, &mut ndarray_rand::rand::thread_rng()),
+    };
+    let params = RirParams::new(
+        rt60,
+        edt,
+        time("itdg", 4.0)?,
+        time("er_duration", 100.0)?,
+        drr,
+    );
+
+    let sample_rate = match query.get("sr") {
+        Some(v) => v
+            .parse()
+            .map_err(|e| (400, format!("Invalid sr '{}': {}", v, e)))?,
+        None => 44100,
+    };
+    if !is_supported_sample_rate(sample_rate) {
+        return Err((
+            400,
+            format!(
+                "sr must be in range [{}, {}]",
+                MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+            ),
+        ));
+    }
+    let algo = query.get("algo").map_or("simple", String::as_str);
+    let rir = build_generator(algo, &params).map_err(|e| (400, e))?;
+
+    let impulse = rir.generate(sample_rate);
+    match query.get("format").map_or("wav", String::as_str) {
+        "wav" => {
+            let mut body = Cursor::new(Vec::new());
+            write_wav_to(&impulse, sample_rate, &mut body)
+                .map_err(|e| (500, e.to_string()))?;
+            Ok(Response::from_data(body.into_inner())
+                .with_header(header("Content-Type", "audio/wav")))
+        }
+        "f32" => {
+            let body: Vec<u8> =
+                impulse.iter().flat_map(|x| x.to_le_bytes()).collect();
+            Ok(Response::from_data(body)
+                .with_header(header("Content-Type", "application/octet-stream")))
+        }
+        other => Err((400, format!("Unknown format '{}'", other))),
+    }
+}
+
+fn analyze(body: impl Read) -> Result<HttpResponse, (u16, String)> {
+    let mut wav = Vec::new();
+    body.take(MAX_BODY + 1)
+        .read_to_end(&mut wav)
+        .map_err(|e| (400, format!("Error reading body: {}", e)))?;
+    if wav.len() as u64 > MAX_BODY {
+        return Err((413, format!("Body exceeds {} bytes", MAX_BODY)));
+    }
+    let (impulse, sample_rate) = read_wav_from(wav.as_slice())
+        .map_err(|e| (400, format!("Invalid wav: {}", e)))?;
+    if impulse.is_empty() {
+        return Err((400, "Empty audio".to_string()));
+    }
+    if !is_supported_sample_rate(sample_rate) {
+        return Err((
+            400,
+            format!(
+                "Sample rate must be in range [{}, {}]",
+                MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+            ),
+        ));
+    }
+    if impulse.iter().any(|x| !x.is_finite()) {
+        return Err((400, "Audio has non-finite samples".to_string()));
+    }
+    let metrics = Metrics::measure(&impulse, sample_rate);
+    let body = format!(
+        "{{\"sample_rate\":{},\"rt60\":{},\"edt\":{},\"drr\":{},\"c50\":{},\
+         \"c80\":{},\"spectral_tilt\":{}}}",
+        sample_rate,
+        json_number(metrics.rt60),
+        json_number(metrics.edt),
+        json_number(Some(metrics.drr)),
+        json_number(Some(metrics.c50)),
+        json_number(Some(metrics.c80)),
+        json_number(metrics.spectral_tilt)
+    );
+    Ok(Response::from_string(body)
+        .with_header(header("Content-Type", "application/json")))
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (percent_decode(key), percent_decode(value)))
+        .collect()
+}
+
+/// Decode `%XX` escapes and `+` for space, malformed escapes are kept as they
+/// are
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_rejects_bad_queries() {
+        for query in [
+            "itdg=-1",
+            "er_duration=-1",
+            "edt=-10",
+            "rt60=NaN",
+            "rt60=inf",
+            "rt60=1e9",
+            "drr=NaN",
+            "rt60=50&edt=50",
+            "sr=100",
+            "algo=other",
+        ] {
+            match generate(&parse_query(query)) {
+                Err((code, _)) => assert_eq!(code, 400, "{}", query),
+                Ok(_) => panic!("'{}' should be rejected", query),
+            }
+        }
+        assert!(generate(&parse_query("rt60=300&edt=30&sr=8000")).is_ok());
+    }
+
+    #[test]
+    fn test_parse_query_decodes_values() {
+        let query = parse_query("drr=%2D3.5&algo=impr%6fved&note=a+b%2&x%3D=1");
+        assert_eq!(query["drr"], "-3.5");
+        assert_eq!(query["algo"], "improved");
+        assert_eq!(query["note"], "a b%2");
+        assert_eq!(query["x="], "1");
+        assert!(generate(&parse_query("drr=%2D3&sr=8000&rt60=300")).is_ok());
+    }
+
+    #[test]
+    fn test_analyze_rejects_bad_bodies() {
+        let wav = |samples: &[f32], sample_rate: u32| {
+            let mut body = Cursor::new(Vec::new());
+            write_wav_to(samples, sample_rate, &mut body).unwrap();
+            body.into_inner()
+        };
+        let impulse: Vec<f32> = (0..8000).map(|i| 0.999_f32.powi(i)).collect();
+        for body in [
+            Vec::new(),
+            b"not a wav".to_vec(),
+            wav(&[], 16000),
+            wav(&impulse, 100),
+        ] {
+            match analyze(body.as_slice()) {
+                Err((code, _)) => assert_eq!(code, 400),
+                Ok(_) => panic!("body should be rejected"),
+            }
+        }
+        let oversized = std::io::repeat(0).take(MAX_BODY + 1);
+        assert!(matches!(analyze(oversized), Err((413, _))));
+        assert!(analyze(wav(&impulse, 16000).as_slice()).is_ok());
+    }
+}
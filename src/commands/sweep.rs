@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+use std::process;
+
+use clap::Args;
+use storir::common::{MAX_SAMPLE_RATE, MIN_SAMPLE_RATE};
+use storir::dsp::{ess, ess_inverse};
+use storir::wav::write_wav;
+
+use super::{parse_duration, EXIT_INVALID_INPUT, EXIT_IO_ERROR};
+
+#[derive(Args, Debug)]
+pub struct SweepArgs {
+    /// Sweep file (wav)
+    #[arg(short, long, default_value = "sweep.wav")]
+    output: PathBuf,
+    /// Also export the inverse filter (wav)
+    #[arg(long, value_name = "FILE")]
+    inverse: Option<PathBuf>,
+    /// Sample rate [8000..=192000 Hz]
+    #[arg(
+        short,
+        long,
+        default_value = "44100",
+        value_parser = clap::value_parser!(u32)
+            .range(MIN_SAMPLE_RATE as i64..=MAX_SAMPLE_RATE as i64)
+    )]
+    sample_rate: u32,
+    /// Start frequency [Hz]
+    #[arg(long, default_value = "20")]
+    f_start: f32,
+    /// End frequency, 20 kHz or Nyquist when lower [Hz]
+    #[arg(long)]
+    f_end: Option<f32>,
+    /// Sweep duration, like `5000`, `5000ms` or `5s` [ms]
+    #[arg(short, long, default_value = "5000", value_parser = parse_duration)]
+    duration: f32,
+}
+
+pub fn run(args: SweepArgs) {
+    let nyquist = args.sample_rate as f32 / 2.0;
+    let f_end = args.f_end.unwrap_or(nyquist.min(20000.0));
+    if !(args.f_start > 0.0 && args.f_start < f_end && f_end <= nyquist) {
+        eprintln!(
+            "Error: frequencies should satisfy 0 < f_start < f_end <= {} Hz",
+            nyquist
+        );
+        process::exit(EXIT_INVALID_INPUT);
+    }
+    if args.duration <= 0.0 {
+        eprintln!("Error: duration should be positive");
+        process::exit(EXIT_INVALID_INPUT);
+    }
+
+    let sweep = ess(args.f_start, f_end, args.duration, args.sample_rate);
+    match write_wav(&sweep, args.sample_rate, &args.output) {
+        Ok(()) => println!(
+            "WAV file '{}' created successfully, {} to {} Hz in {} ms.",
+            args.output.display(),
+            args.f_start,
+            f_end,
+            args.duration
+        ),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(EXIT_IO_ERROR);
+        }
+    }
+
+    if let Some(path) = &args.inverse {
+        let mut inverse =
+            ess_inverse(args.f_start, f_end, args.duration, args.sample_rate);
+        // Inverse filter is far below full scale, store it normalized
+        let peak = inverse.iter().fold(0.0_f32, |m, x| m.max(x.abs()));
+        if peak > 0.0 {
+            inverse.iter_mut().for_each(|x| *x /= peak);
+        }
+        match write_wav(&inverse, args.sample_rate, path) {
+            Ok(()) => println!(
+                "WAV file '{}' created successfully, apply {:.2} dB gain to \
+                 restore the level.",
+                path.display(),
+                20.0 * peak.log10()
+            ),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(EXIT_IO_ERROR);
+            }
+        }
+    }
+}
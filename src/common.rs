@@ -1,8 +1,33 @@
+/// Lowest supported sample rate [Hz] (telephony band)
+pub const MIN_SAMPLE_RATE: u32 = 8_000;
+/// Highest supported sample rate [Hz]
+pub const MAX_SAMPLE_RATE: u32 = 192_000;
+
+/// Check if sample rate is within supported range
+pub fn is_supported_sample_rate(sample_rate: u32) -> bool {
+    (MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate)
+}
+
 /// Convert units from decibels to gain
 pub fn decibels_to_gain(decibels: f32) -> f32 {
     10.0_f32.powf(decibels / 20.0)
 }
 
+/// Check that a time parameter [ms] is usable, finite and not negative
+pub fn is_valid_duration(ms: f32) -> bool {
+    ms.is_finite() && ms >= 0.0
+}
+
+/// Number of samples in fractional milliseconds
+pub fn ms_to_samples(ms: f32, sample_rate: u32) -> usize {
+    (ms as f64 / 1000.0 * sample_rate as f64).round() as usize
+}
+
+/// Sum samples using a double precision accumulator
+pub fn sum_f64<'a>(samples: impl IntoIterator<Item = &'a f32>) -> f64 {
+    samples.into_iter().map(|&x| x as f64).sum()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -28,4 +53,12 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_sum_f64() {
+        // 10 seconds at 192 kHz, an f32 accumulator drifts far away from this
+        let samples = vec![0.1_f32; 1_920_000];
+        let result = sum_f64(&samples);
+        assert!((result - 192_000.0).abs() < 1e-2, "Result: {}", result);
+    }
 }
@@ -0,0 +1,197 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// Decay curve of the impulse response, level [dB] over time [ms] from the
+/// start of the response, replaces the built-in EDT/RT60 slopes.
+///
+/// Levels are relative, the loudest point becomes the direct sound. Length of
+/// the response still comes from rt60.
+#[derive(Clone)]
+pub enum DecayProfile {
+    /// Any function of time [ms] returning level [dB]
+    Custom(Arc<dyn Fn(f32) -> f32 + Send + Sync>),
+    /// Levels [dB] sampled every `interval` [ms] starting at 0, linearly
+    /// interpolated, the last level holds after the end
+    Sampled { interval: f32, levels: Vec<f32> },
+    /// Coupled rooms (e.g. church with side chapels): fast decay with
+    /// reverberation time `first` [ms] until the `knee` [ms], slower decay
+    /// with reverberation time `second` [ms] afterwards
+    DoubleSlope { first: f32, knee: f32, second: f32 },
+    /// Breakpoints (time [ms], level [dB]) linearly interpolated in dB,
+    /// the last segment continues after the end
+    Piecewise(Vec<(f32, f32)>),
+}
+
+impl DecayProfile {
+    pub fn custom<F: Fn(f32) -> f32 + Send + Sync + 'static>(envelope: F) -> Self {
+        DecayProfile::Custom(Arc::new(envelope))
+    }
+
+    pub fn sampled(interval: f32, levels: Vec<f32>) -> Self {
+        if interval <= 0.0 {
+            panic!("Sampling interval of the decay curve should be positive")
+        };
+        if levels.is_empty() {
+            panic!("Decay curve needs at least one level")
+        };
+        DecayProfile::Sampled { interval, levels }
+    }
+
+    pub fn double_slope(first: f32, knee: f32, second: f32) -> Self {
+        if first <= 0.0 || second <= 0.0 {
+            panic!("Reverberation times of both slopes should be positive")
+        };
+        if knee < 0.0 {
+            panic!("Knee point can't be negative")
+        };
+        DecayProfile::DoubleSlope {
+            first,
+            knee,
+            second,
+        }
+    }
+
+    /// Breakpoints have to decay monotonically: times never go back and
+    /// levels never rise, equal times make an instant drop
+    pub fn piecewise(breakpoints: Vec<(f32, f32)>) -> Self {
+        if breakpoints.is_empty() {
+            panic!("Decay curve needs at least one breakpoint")
+        };
+        for pair in breakpoints.windows(2) {
+            let ((t0, l0), (t1, l1)) = (pair[0], pair[1]);
+            if t1 < t0 {
+                panic!("Breakpoint times should be increasing ({} > {})", t0, t1)
+            };
+            if l1 > l0 {
+                panic!("Breakpoint levels should be decaying ({} < {})", l0, l1)
+            };
+        }
+        DecayProfile::Piecewise(breakpoints)
+    }
+
+    /// Built-in EDT/RT60 slopes, 10 dB drop over `edt` [ms] followed by
+    /// 50 dB per `rt60` [ms]
+    pub fn edt_rt60(edt: f32, rt60: f32) -> Self {
+        Self::piecewise(vec![(0.0, 0.0), (edt, -10.0), (edt + rt60, -60.0)])
+    }
+
+    /// Level at `time` [ms] [dB]
+    pub fn level(&self, time: f32) -> f32 {
+        match self {
+            DecayProfile::Custom(envelope) => envelope(time),
+            DecayProfile::Sampled { interval, levels } => {
+                let position = (time / interval).max(0.0);
+                let index = position.floor() as usize;
+                if index + 1 >= levels.len() {
+                    return levels[levels.len() - 1];
+                }
+                let fraction = position - index as f32;
+                levels[index] + (levels[index + 1] - levels[index]) * fraction
+            }
+            DecayProfile::DoubleSlope {
+                first,
+                knee,
+                second,
+            } => {
+                if time < *knee {
+                    -60.0 * time / first
+                } else {
+                    -60.0 * knee / first - 60.0 * (time - knee) / second
+                }
+            }
+            DecayProfile::Piecewise(breakpoints) => {
+                let interpolate = |(t0, l0): (f32, f32), (t1, l1): (f32, f32)| {
+                    if t1 > t0 {
+                        l0 + (l1 - l0) * (time - t0) / (t1 - t0)
+                    } else {
+                        l1
+                    }
+                };
+                match breakpoints.iter().position(|&(t, _)| t > time) {
+                    Some(0) => breakpoints[0].1,
+                    Some(i) => interpolate(breakpoints[i - 1], breakpoints[i]),
+                    None if breakpoints.len() > 1 => interpolate(
+                        breakpoints[breakpoints.len() - 2],
+                        breakpoints[breakpoints.len() - 1],
+                    ),
+                    None => breakpoints[0].1,
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for DecayProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecayProfile::Custom(_) => f.write_str("Custom(..)"),
+            DecayProfile::Sampled { interval, levels } => f
+                .debug_struct("Sampled")
+                .field("interval", interval)
+                .field("levels", levels)
+                .finish(),
+            DecayProfile::DoubleSlope {
+                first,
+                knee,
+                second,
+            } => f
+                .debug_struct("DoubleSlope")
+                .field("first", first)
+                .field("knee", knee)
+                .field("second", second)
+                .finish(),
+            DecayProfile::Piecewise(breakpoints) => {
+                f.debug_tuple("Piecewise").field(breakpoints).finish()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampled_level() {
+        let profile = DecayProfile::sampled(10.0, vec![0.0, -10.0, -40.0]);
+        assert_eq!(profile.level(0.0), 0.0);
+        assert_eq!(profile.level(5.0), -5.0);
+        assert_eq!(profile.level(15.0), -25.0);
+        assert_eq!(profile.level(100.0), -40.0);
+
+        let profile = DecayProfile::custom(|t| -t / 10.0);
+        assert_eq!(profile.level(100.0), -10.0);
+    }
+
+    #[test]
+    fn test_double_slope_level() {
+        let profile = DecayProfile::double_slope(300.0, 100.0, 1200.0);
+        assert_eq!(profile.level(50.0), -10.0);
+        assert_eq!(profile.level(100.0), -20.0);
+        assert_eq!(profile.level(400.0), -35.0);
+    }
+
+    #[test]
+    fn test_piecewise_level() {
+        let profile = DecayProfile::piecewise(vec![
+            (10.0, 0.0),
+            (20.0, -10.0),
+            (40.0, -20.0),
+        ]);
+        assert_eq!(profile.level(0.0), 0.0);
+        assert_eq!(profile.level(15.0), -5.0);
+        assert_eq!(profile.level(30.0), -15.0);
+        // Last segment continues
+        assert_eq!(profile.level(60.0), -30.0);
+
+        let profile = DecayProfile::edt_rt60(50.0, 500.0);
+        assert_eq!(profile.level(50.0), -10.0);
+        assert_eq!(profile.level(550.0), -60.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_piecewise_rising_level() {
+        DecayProfile::piecewise(vec![(0.0, -10.0), (10.0, 0.0)]);
+    }
+}
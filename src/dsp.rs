@@ -0,0 +1,837 @@
+use ndarray_rand::rand::Rng;
+use ndarray_rand::rand_distr::StandardNormal;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+use crate::analysis::{
+    crest_factor, find_onset, flattened, NoiseFloor, ENVELOPE_WINDOW,
+};
+use crate::common::{decibels_to_gain, ms_to_samples};
+
+/// Zero crossings on each side of the resampling kernel
+const RESAMPLE_ZERO_CROSSINGS: f64 = 32.0;
+/// Zero crossings on each side of the fractional delay kernel
+const FRACTIONAL_DELAY_ZERO_CROSSINGS: f64 = 8.0;
+/// Delays of the diffusion allpass cascade [ms]
+const DIFFUSION_DELAYS: [f32; 4] = [2.3, 1.7, 1.1, 0.7];
+/// Allpass gain at full diffusion
+const MAX_DIFFUSION_GAIN: f32 = 0.6;
+/// Raised cosine fades at both ends of sweeps [ms], avoid clicks on playback
+const SWEEP_FADE: f32 = 10.0;
+
+/// Power spectrum of the signal, bins from DC up to Nyquist.
+///
+/// Bin `k` corresponds to frequency `k * sample_rate / data.len()`.
+pub fn power_spectrum(data: &[f32]) -> Vec<f32> {
+    let mut buffer = zero_padded(data, data.len());
+    FftPlanner::new()
+        .plan_fft_forward(buffer.len())
+        .process(&mut buffer);
+    buffer
+        .iter()
+        .take(data.len() / 2 + 1)
+        .map(|c| c.norm_sqr())
+        .collect()
+}
+
+/// Linear convolution of the signal with impulse response (FFT based),
+/// output has `signal.len() + ir.len() - 1` samples
+pub fn convolve(signal: &[f32], ir: &[f32]) -> Vec<f32> {
+    if signal.is_empty() || ir.is_empty() {
+        return Vec::new();
+    }
+    let len = signal.len() + ir.len() - 1;
+    let fft_len = len.next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(fft_len);
+    let ifft = planner.plan_fft_inverse(fft_len);
+
+    let mut a = zero_padded(signal, fft_len);
+    let mut b = zero_padded(ir, fft_len);
+    fft.process(&mut a);
+    fft.process(&mut b);
+    for (x, y) in a.iter_mut().zip(&b) {
+        *x *= y;
+    }
+    ifft.process(&mut a);
+
+    let scale = 1.0 / fft_len as f32;
+    a.iter().take(len).map(|c| c.re * scale).collect()
+}
+
+/// Add noise to the signal at given signal to noise ratio [dB], noise is
+/// repeated when it is shorter than the signal
+pub fn mix_at_snr(signal: &[f32], noise: &[f32], snr: f32) -> Vec<f32> {
+    let energy = |data: &mut dyn Iterator<Item = &f32>| -> f64 {
+        data.map(|&x| (x as f64).powi(2)).sum()
+    };
+    // Both energies are taken over the same number of samples
+    let signal_energy = energy(&mut signal.iter());
+    let noise_energy = energy(&mut noise.iter().cycle().take(signal.len()));
+    if noise_energy == 0.0 {
+        return signal.to_vec();
+    }
+
+    let gain = (signal_energy / noise_energy / 10.0_f64.powf(snr as f64 / 10.0))
+        .sqrt() as f32;
+    signal
+        .iter()
+        .zip(noise.iter().cycle())
+        .map(|(&s, &n)| s + gain * n)
+        .collect()
+}
+
+/// Magnitude of the analytic signal (Hilbert transform), instantaneous
+/// amplitude of the signal
+pub fn hilbert_magnitude(data: &[f32]) -> Vec<f32> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    // Padding keeps the end of the response from wrapping onto its start
+    let fft_len = (2 * data.len()).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let mut spectrum = zero_padded(data, fft_len);
+    planner.plan_fft_forward(fft_len).process(&mut spectrum);
+    // Keep DC and Nyquist, double positive and drop negative frequencies
+    for (k, c) in spectrum.iter_mut().enumerate() {
+        if k > fft_len / 2 {
+            *c = Complex::new(0.0, 0.0);
+        } else if k > 0 && k < fft_len / 2 {
+            *c *= 2.0;
+        }
+    }
+    planner.plan_fft_inverse(fft_len).process(&mut spectrum);
+
+    let scale = 1.0 / fft_len as f32;
+    spectrum
+        .iter()
+        .take(data.len())
+        .map(|c| c.norm() * scale)
+        .collect()
+}
+
+/// RMS over the centered moving window of `window` samples
+pub fn local_rms(data: &[f32], window: usize) -> Vec<f32> {
+    let half = window / 2;
+    let mut cumulative = Vec::with_capacity(data.len() + 1);
+    cumulative.push(0.0_f64);
+    for &x in data {
+        cumulative.push(cumulative.last().unwrap() + (x as f64).powi(2));
+    }
+    (0..data.len())
+        .map(|i| {
+            let start = i.saturating_sub(half);
+            let end = (i + half + 1).min(data.len());
+            ((cumulative[end] - cumulative[start]) / (end - start) as f64).sqrt()
+                as f32
+        })
+        .collect()
+}
+
+/// Smooth spiky tail starting at `start` until its crest factor (with decay
+/// envelope removed) drops to `crest_factor_target`.
+///
+/// Energy of every ray is spread over neighbouring samples with a Hann
+/// kernel, the narrowest kernel (up to `ENVELOPE_WINDOW`) hitting the target
+/// is searched by bisection and the local energy envelope stays the same.
+/// Tails already below the target are left untouched, very sparse tails may
+/// not reach it.
+pub fn redistribute_tail(
+    ir: &mut [f32],
+    start: usize,
+    crest_factor_target: f32,
+    sample_rate: u32,
+) {
+    let start = start.min(ir.len());
+    let tail = &ir[start..];
+    let crest = |data: &[f32]| crest_factor(&flattened(data, sample_rate));
+    if tail.is_empty() || crest(tail) <= crest_factor_target {
+        return;
+    }
+
+    let window = ms_to_samples(ENVELOPE_WINDOW, sample_rate).max(2);
+    let envelope = local_rms(tail, window);
+    let smear = |width: usize| -> Vec<f32> {
+        let kernel: Vec<f32> = (0..width)
+            .map(|i| {
+                let phase =
+                    std::f32::consts::PI * (i + 1) as f32 / (width + 1) as f32;
+                phase.sin().powi(2)
+            })
+            .collect();
+        let smeared: Vec<f32> = convolve(tail, &kernel)
+            .into_iter()
+            .skip(width / 2)
+            .take(tail.len())
+            .collect();
+        let smeared_envelope = local_rms(&smeared, window);
+        smeared
+            .iter()
+            .zip(smeared_envelope.iter().zip(&envelope))
+            .map(|(&x, (&m, &e))| if m > 0.0 { x * e / m } else { 0.0 })
+            .collect()
+    };
+
+    // Wider kernel, lower crest factor
+    let (mut low, mut high) = (1, window);
+    while high - low > 1 {
+        let width = (low + high) / 2;
+        if crest(&smear(width)) > crest_factor_target {
+            low = width;
+        } else {
+            high = width;
+        }
+    }
+
+    let result = smear(high);
+    ir[start..].copy_from_slice(&result);
+}
+
+/// Scale the response by `gain` [dB], e.g. -6 leaves 6 dB of headroom
+pub fn apply_gain(data: &mut [f32], gain: f32) {
+    let gain = decibels_to_gain(gain);
+    data.iter_mut().for_each(|x| *x *= gain);
+}
+
+/// Amplitudes of generated energies for convolution, square roots with
+/// random signs, the first sample (direct sound) stays positive
+pub fn signed_amplitudes<R: Rng + ?Sized>(
+    energies: &[f32],
+    rng: &mut R,
+) -> Vec<f32> {
+    energies
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            let amplitude = x.abs().sqrt();
+            match i == 0 || rng.gen_bool(0.5) {
+                true => amplitude,
+                false => -amplitude,
+            }
+        })
+        .collect()
+}
+
+/// Number of samples outside [-1, 1], which clip in integer PCM
+pub fn clipped_samples(data: &[f32]) -> usize {
+    data.iter().filter(|x| x.abs() > 1.0).count()
+}
+
+/// Increase echo density of the tail starting at `start` with a cascade of
+/// Schroeder allpass filters, `diffusion` in [0, 1] scales their gains.
+///
+/// Allpasses are lossless and the delays are a few ms, so the decay envelope
+/// stays while the tail gets smoother (and bipolar). The filters run over the
+/// whole response, so the tail starts without a transient.
+pub fn diffuse(ir: &mut [f32], start: usize, diffusion: f32, sample_rate: u32) {
+    let start = start.min(ir.len());
+    if diffusion <= 0.0 || start == ir.len() {
+        return;
+    }
+    let gain = diffusion * MAX_DIFFUSION_GAIN;
+    let mut signal = ir.to_vec();
+    for delay in DIFFUSION_DELAYS {
+        let delay = ms_to_samples(delay, sample_rate).max(1);
+        let mut output = vec![0.0; signal.len()];
+        for n in 0..signal.len() {
+            output[n] = -gain * signal[n];
+            if n >= delay {
+                output[n] += signal[n - delay] + gain * output[n - delay];
+            }
+        }
+        signal = output;
+    }
+    ir[start..].copy_from_slice(&signal[start..]);
+}
+
+/// Shift the response so that its onset (see `find_onset`) lands at
+/// `sample`, leading samples are trimmed or zeros prepended and the length
+/// changes by the shift. Silent responses are returned as they are.
+pub fn align(ir: &[f32], sample: usize) -> Vec<f32> {
+    match find_onset(ir) {
+        Some(onset) if onset > sample => ir[onset - sample..].to_vec(),
+        Some(onset) => {
+            let mut aligned = vec![0.0; sample - onset];
+            aligned.extend_from_slice(ir);
+            aligned
+        }
+        None => ir.to_vec(),
+    }
+}
+
+/// Minimum-phase equivalent of the impulse response (cepstral method).
+///
+/// Magnitude response stays the same while energy moves as early as possible,
+/// useful for low-latency convolution and equalization. Output has the same
+/// length as the input.
+pub fn to_minimum_phase(ir: &[f32]) -> Vec<f32> {
+    if ir.is_empty() {
+        return Vec::new();
+    }
+    // Generous padding keeps cepstral aliasing low
+    let fft_len = (4 * ir.len()).max(4096).next_power_of_two();
+    let mut planner = FftPlanner::<f64>::new();
+    let fft = planner.plan_fft_forward(fft_len);
+    let ifft = planner.plan_fft_inverse(fft_len);
+    let scale = 1.0 / fft_len as f64;
+
+    let mut spectrum = vec![Complex::new(0.0, 0.0); fft_len];
+    for (c, &x) in spectrum.iter_mut().zip(ir) {
+        c.re = x as f64;
+    }
+    fft.process(&mut spectrum);
+
+    // Real cepstrum of the log magnitude, floor avoids log of zero
+    let floor = 1e-10 * spectrum.iter().map(|c| c.norm()).fold(0.0, f64::max);
+    let mut cepstrum: Vec<Complex<f64>> = spectrum
+        .iter()
+        .map(|c| Complex::new(c.norm().max(floor).ln(), 0.0))
+        .collect();
+    ifft.process(&mut cepstrum);
+
+    // Fold anti-causal part onto the causal one
+    for (n, c) in cepstrum.iter_mut().enumerate() {
+        let fold = match n {
+            0 => 1.0,
+            n if n < fft_len / 2 => 2.0,
+            n if n == fft_len / 2 => 1.0,
+            _ => 0.0,
+        };
+        *c = Complex::new(c.re * scale * fold, 0.0);
+    }
+    fft.process(&mut cepstrum);
+    for c in cepstrum.iter_mut() {
+        *c = c.exp();
+    }
+    ifft.process(&mut cepstrum);
+
+    cepstrum
+        .iter()
+        .take(ir.len())
+        .map(|c| (c.re * scale) as f32)
+        .collect()
+}
+
+/// Regularized inverse filter of the impulse response, `length` samples.
+///
+/// Computed in the frequency domain as `conj(H) / (|H|^2 + beta)` with
+/// `beta = regularization * max(|H|^2)`, larger regularization trades
+/// inversion accuracy for less boost at spectral notches. A modeling delay
+/// of `length / 2` samples lets the filter have a non-causal part, so
+/// `convolve(ir, inverse)` approximates an impulse at `length / 2`.
+pub fn inverse_filter(ir: &[f32], length: usize, regularization: f32) -> Vec<f32> {
+    if ir.is_empty() || length == 0 {
+        return Vec::new();
+    }
+    let fft_len = (ir.len() + length).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(fft_len);
+    let ifft = planner.plan_fft_inverse(fft_len);
+
+    let mut spectrum = zero_padded(ir, fft_len);
+    fft.process(&mut spectrum);
+    let max_power = spectrum.iter().map(|c| c.norm_sqr()).fold(0.0, f32::max);
+    let beta = regularization * max_power;
+
+    let delay = length / 2;
+    for (k, c) in spectrum.iter_mut().enumerate() {
+        let phase = -2.0 * std::f32::consts::PI * (k * delay % fft_len) as f32
+            / fft_len as f32;
+        let shift = Complex::from_polar(1.0, phase);
+        *c = c.conj() / (c.norm_sqr() + beta) * shift;
+    }
+    ifft.process(&mut spectrum);
+
+    let scale = 1.0 / fft_len as f32;
+    spectrum.iter().take(length).map(|c| c.re * scale).collect()
+}
+
+/// Response of the system that turned `excitation` into `recording`,
+/// `recording.len()` samples.
+///
+/// Regularized spectral division `Y * conj(X) / (|X|^2 + beta)` with
+/// `beta = regularization * max(|X|^2)`, which keeps frequencies the
+/// excitation does not cover from being boosted. Responses at negative lags
+/// (e.g. harmonic distortion of sweeps) end up past the returned samples.
+pub fn deconvolve(
+    recording: &[f32],
+    excitation: &[f32],
+    regularization: f32,
+) -> Vec<f32> {
+    if recording.is_empty() || excitation.is_empty() {
+        return Vec::new();
+    }
+    let fft_len = (recording.len() + excitation.len()).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(fft_len);
+    let ifft = planner.plan_fft_inverse(fft_len);
+
+    let mut output = zero_padded(recording, fft_len);
+    let mut input = zero_padded(excitation, fft_len);
+    fft.process(&mut output);
+    fft.process(&mut input);
+    let max_power = input.iter().map(|c| c.norm_sqr()).fold(0.0, f32::max);
+    let beta = regularization * max_power;
+    for (y, x) in output.iter_mut().zip(&input) {
+        *y = *y * x.conj() / (x.norm_sqr() + beta);
+    }
+    ifft.process(&mut output);
+
+    let scale = 1.0 / fft_len as f32;
+    output
+        .iter()
+        .take(recording.len())
+        .map(|c| c.re * scale)
+        .collect()
+}
+
+/// Exponential sine sweep (Farina) from `f_start` to `f_end` [Hz] lasting
+/// `duration` [ms], full scale with short fades at both ends.
+///
+/// Every octave takes the same time, so the sweep has a pink spectrum and
+/// harmonic distortion separates from the linear response on deconvolution.
+pub fn ess(f_start: f32, f_end: f32, duration: f32, sample_rate: u32) -> Vec<f32> {
+    let len = sweep_len(f_start, f_end, duration, sample_rate);
+    let (f_start, duration) = (f_start as f64, duration as f64 / 1000.0);
+    let rate = (f_end as f64 / f_start).ln();
+    let fade = ms_to_samples(SWEEP_FADE, sample_rate).min(len / 2);
+    (0..len)
+        .map(|i| {
+            let t = i as f64 / sample_rate as f64;
+            let phase = 2.0 * std::f64::consts::PI * f_start * duration / rate
+                * ((t * rate / duration).exp() - 1.0);
+            let edge = i.min(len - 1 - i);
+            let gain = if edge < fade {
+                0.5 - 0.5 * (std::f64::consts::PI * edge as f64 / fade as f64).cos()
+            } else {
+                1.0
+            };
+            (phase.sin() * gain) as f32
+        })
+        .collect()
+}
+
+/// Inverse filter of the `ess` sweep with the same arguments, the time
+/// reversed sweep with amplitude rising 6 dB per octave of frequency to
+/// flatten its pink spectrum.
+///
+/// `convolve(recording, inverse)` gives the impulse response starting at
+/// sample `inverse.len() - 1`, scaled so a sweep played straight into the
+/// recording gives a unit peak.
+pub fn ess_inverse(
+    f_start: f32,
+    f_end: f32,
+    duration: f32,
+    sample_rate: u32,
+) -> Vec<f32> {
+    let sweep = ess(f_start, f_end, duration, sample_rate);
+    let rate = (f_end as f64 / f_start as f64).ln();
+    let samples = duration as f64 / 1000.0 * sample_rate as f64;
+    let weights: Vec<f64> = (0..sweep.len())
+        .map(|i| (i as f64 * rate / samples).exp())
+        .collect();
+    let peak: f64 = sweep
+        .iter()
+        .zip(&weights)
+        .map(|(&x, w)| (x as f64).powi(2) * w)
+        .sum();
+    sweep
+        .iter()
+        .zip(&weights)
+        .rev()
+        .map(|(&x, w)| (x as f64 * w / peak) as f32)
+        .collect()
+}
+
+/// Number of sweep samples, panics on invalid sweep arguments
+fn sweep_len(f_start: f32, f_end: f32, duration: f32, sample_rate: u32) -> usize {
+    if !(f_start > 0.0 && f_start < f_end && f_end <= sample_rate as f32 / 2.0) {
+        panic!("Sweep should satisfy 0 < f_start < f_end <= sample_rate / 2")
+    };
+    if duration.is_nan() || duration <= 0.0 {
+        panic!("Sweep duration should be positive")
+    };
+    ms_to_samples(duration, sample_rate).max(1)
+}
+
+/// Replace the noise floor of a measured impulse response with a stochastic
+/// tail decaying at the measured rate, down to `depth` [dB] below the
+/// envelope peak.
+///
+/// Gaussian noise shaped by the fitted decay is crossfaded in (equal power,
+/// `ENVELOPE_WINDOW` long) right before the noise floor starts, the output
+/// gets longer or shorter depending on how deep the tail goes. None when the
+/// decay rate is not finite and negative (the measured tail doesn't decay)
+/// or the depth is not finite.
+pub fn extend_tail<R: Rng + ?Sized>(
+    ir: &[f32],
+    floor: &NoiseFloor,
+    depth: f32,
+    sample_rate: u32,
+    rng: &mut R,
+) -> Option<Vec<f32>> {
+    if !(floor.decay_rate.is_finite()
+        && floor.decay_rate < 0.0
+        && depth.is_finite())
+    {
+        return None;
+    }
+    let window = ms_to_samples(ENVELOPE_WINDOW, sample_rate);
+    let peak = local_rms(ir, window).into_iter().fold(0.0_f32, f32::max);
+    let rate = floor.decay_rate as f64 * 1000.0 / sample_rate as f64;
+    let start = floor.start.min(ir.len());
+    let end =
+        start + ((-depth as f64 - floor.level as f64) / rate).max(0.0) as usize;
+    let fade_start = start.saturating_sub(window);
+
+    let extended = (0..end)
+        .map(|i| {
+            let measured = ir.get(i).copied().unwrap_or(0.0);
+            if i < fade_start {
+                return measured;
+            }
+            let level = floor.level as f64 + rate * (i as f64 - start as f64);
+            let noise: f64 = rng.sample(StandardNormal);
+            let synthetic =
+                (peak as f64 * 10.0_f64.powf(level / 20.0) * noise) as f32;
+            if i >= start {
+                return synthetic;
+            }
+            let phase = (i - fade_start) as f32 / (start - fade_start) as f32
+                * std::f32::consts::FRAC_PI_2;
+            measured * phase.cos() + synthetic * phase.sin()
+        })
+        .collect();
+    Some(extended)
+}
+
+/// Band-limited resampling from `from` to `to` sample rate [Hz].
+///
+/// Hann windowed sinc interpolation with cutoff at the lower Nyquist
+/// frequency, so downsampling doesn't alias. Output keeps the duration,
+/// `ceil(len * to / from)` samples.
+pub fn resample(data: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to {
+        return data.to_vec();
+    }
+    let ratio = to as f64 / from as f64;
+    let len = (data.len() as f64 * ratio).ceil() as usize;
+    // Cutoff relative to the input Nyquist frequency
+    let cutoff = ratio.min(1.0);
+    let half_width = RESAMPLE_ZERO_CROSSINGS / cutoff;
+
+    (0..len)
+        .map(|n| {
+            let center = n as f64 / ratio;
+            let first = (center - half_width).ceil().max(0.0) as usize;
+            let last = ((center + half_width).floor() as usize)
+                .min(data.len().saturating_sub(1));
+            (first..=last)
+                .map(|k| {
+                    let t = cutoff * (k as f64 - center);
+                    data[k] as f64
+                        * cutoff
+                        * windowed_sinc(t, RESAMPLE_ZERO_CROSSINGS)
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+/// Add `value` at fractional sample `position` spread with a windowed sinc
+/// kernel (band limited delay), taps outside of the data are dropped
+pub fn add_fractional(data: &mut [f32], position: f64, value: f32) {
+    let first =
+        (position - FRACTIONAL_DELAY_ZERO_CROSSINGS).ceil().max(0.0) as usize;
+    let last = ((position + FRACTIONAL_DELAY_ZERO_CROSSINGS).floor() as usize)
+        .min(data.len().saturating_sub(1));
+    for (k, x) in data.iter_mut().enumerate().take(last + 1).skip(first) {
+        let t = k as f64 - position;
+        *x += (value as f64 * windowed_sinc(t, FRACTIONAL_DELAY_ZERO_CROSSINGS))
+            as f32;
+    }
+}
+
+/// Hann windowed sinc at `t` samples from the center of a kernel with
+/// `zero_crossings` on each side
+fn windowed_sinc(t: f64, zero_crossings: f64) -> f64 {
+    let x = std::f64::consts::PI * t;
+    let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+    sinc * (0.5 + 0.5 * (x / zero_crossings).cos())
+}
+
+fn zero_padded(data: &[f32], len: usize) -> Vec<Complex<f32>> {
+    let mut buffer = vec![Complex::new(0.0, 0.0); len];
+    for (c, &x) in buffer.iter_mut().zip(data) {
+        c.re = x;
+    }
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_spectrum() {
+        // Cosine right in the middle of bin 4
+        let data: Vec<f32> = (0..64)
+            .map(|i| (2.0 * std::f32::consts::PI * 4.0 * i as f32 / 64.0).cos())
+            .collect();
+        let spectrum = power_spectrum(&data);
+        assert_eq!(spectrum.len(), 33);
+        let peak = spectrum
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i);
+        assert_eq!(peak, Some(4));
+    }
+
+    #[test]
+    fn test_convolve() {
+        let result = convolve(&[1.0, 2.0, 3.0], &[0.0, 1.0, 0.5]);
+        let expected = [0.0, 1.0, 2.5, 4.0, 1.5];
+        assert_eq!(result.len(), expected.len());
+        for (r, e) in result.iter().zip(expected) {
+            assert!((r - e).abs() < 1e-5, "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn test_mix_at_snr() {
+        let signal: Vec<f32> = (0..1000).map(|i| (i as f32 * 0.1).sin()).collect();
+        let noise: Vec<f32> = (0..300).map(|i| (i as f32 * 1.7).cos()).collect();
+        let mixed = mix_at_snr(&signal, &noise, 10.0);
+        assert_eq!(mixed.len(), signal.len());
+
+        let added: Vec<f32> =
+            mixed.iter().zip(&signal).map(|(m, s)| m - s).collect();
+        let power = |d: &[f32]| d.iter().map(|x| x * x).sum::<f32>();
+        let snr = 10.0 * (power(&signal) / power(&added)).log10();
+        assert!((snr - 10.0).abs() < 0.01, "snr: {}", snr);
+    }
+
+    #[test]
+    fn test_redistribute_tail() {
+        use crate::analysis::tail_statistics;
+
+        // Sparse decaying tail, every 16th sample is a ray
+        let sample_rate = 16000;
+        let mut ir: Vec<f32> = (0..sample_rate)
+            .map(|i| {
+                if i % 16 == 0 {
+                    (-(i as f32) / 2000.0).exp()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let before = tail_statistics(&ir, sample_rate).unwrap();
+        assert!(before.crest_factor > 4.0, "{:?}", before);
+
+        redistribute_tail(&mut ir, 1280, 4.0, sample_rate);
+        let after = tail_statistics(&ir, sample_rate).unwrap();
+        assert!(after.crest_factor <= 4.0, "{:?}", after);
+    }
+
+    #[test]
+    fn test_apply_gain() {
+        let mut data = vec![1.0, -0.5, 0.25];
+        apply_gain(&mut data, 6.0);
+        assert_eq!(clipped_samples(&data), 1);
+        apply_gain(&mut data, -12.0);
+        assert!((data[0] - 0.5).abs() < 1e-2, "{:?}", data);
+        assert_eq!(clipped_samples(&data), 0);
+    }
+
+    #[test]
+    fn test_signed_amplitudes() {
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        let energies: Vec<f32> = (0..1000).map(|i| 0.99_f32.powi(i)).collect();
+        let amplitudes =
+            signed_amplitudes(&energies, &mut StdRng::seed_from_u64(1));
+        assert_eq!(amplitudes[0], 1.0);
+        for (energy, amplitude) in energies.iter().zip(&amplitudes) {
+            assert!((amplitude * amplitude - energy).abs() < 1e-6);
+        }
+        let negative = amplitudes.iter().filter(|&&x| x < 0.0).count();
+        assert!((400..600).contains(&negative), "negative: {}", negative);
+    }
+
+    #[test]
+    fn test_diffuse() {
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        // Sparse exponentially decaying tail, a ray every 5 ms
+        let sample_rate = 16000;
+        let mut rng = StdRng::seed_from_u64(9);
+        let ir: Vec<f32> = (0..8000)
+            .map(|i| {
+                if i % 80 == 0 {
+                    rng.gen_range(0.5..1.0) * (-(i as f32) / 1500.0).exp()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let mut diffused = ir.clone();
+        diffuse(&mut diffused, 1280, 1.0, sample_rate);
+        assert_eq!(diffused[..1280], ir[..1280]);
+
+        let density = |d: &[f32]| d.iter().filter(|x| x.abs() > 1e-6).count();
+        assert!(density(&diffused[1280..]) > 10 * density(&ir[1280..]));
+        // Decay stays, energy of 100 ms blocks within 1.5 dB
+        let energy = |d: &[f32]| d.iter().map(|x| x * x).sum::<f32>();
+        for block in (1600..6400).step_by(1600) {
+            let ratio = energy(&diffused[block..block + 1600])
+                / energy(&ir[block..block + 1600]);
+            assert!(10.0 * ratio.log10().abs() < 1.5, "{}: {}", block, ratio);
+        }
+    }
+
+    #[test]
+    fn test_align() {
+        let mut ir = vec![0.0; 100];
+        ir[40] = 1.0;
+        ir[60] = 0.5;
+
+        let trimmed = align(&ir, 10);
+        assert_eq!(trimmed.len(), 70);
+        assert_eq!((trimmed[10], trimmed[30]), (1.0, 0.5));
+
+        let padded = align(&ir, 64);
+        assert_eq!(padded.len(), 124);
+        assert_eq!((padded[64], padded[84]), (1.0, 0.5));
+
+        assert_eq!(align(&[0.0; 8], 4), vec![0.0; 8]);
+    }
+
+    #[test]
+    fn test_to_minimum_phase() {
+        // Maximum-phase pair turns around, magnitude response is the same
+        let result = to_minimum_phase(&[0.5, 1.0]);
+        assert!((result[0] - 1.0).abs() < 1e-3, "{:?}", result);
+        assert!((result[1] - 0.5).abs() < 1e-3, "{:?}", result);
+    }
+
+    #[test]
+    fn test_extend_tail() {
+        use crate::analysis::noise_floor;
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        // 60 dB drop in 500 ms buried in noise 40 dB below the peak
+        let sample_rate = 16000;
+        let mut rng = StdRng::seed_from_u64(3);
+        let decay = -60.0 / (0.5 * sample_rate as f32);
+        let ir: Vec<f32> = (0..sample_rate)
+            .map(|i| {
+                let x: f32 = rng.sample(StandardNormal);
+                let n: f32 = rng.sample(StandardNormal);
+                x * 10.0_f32.powf(decay * i as f32 / 20.0) + 0.01 * n
+            })
+            .collect();
+        let floor = noise_floor(&ir, sample_rate).unwrap();
+        let extended =
+            extend_tail(&ir, &floor, 80.0, sample_rate, &mut rng).unwrap();
+        // Floor at -40 dB, 40 dB more take another 333 ms
+        assert!((extended.len() as i64 - 10667).abs() < 800, "{:?}", floor);
+
+        let floor_after = noise_floor(&extended, sample_rate);
+        assert!(
+            floor_after.is_none_or(|f| f.level < -70.0),
+            "{:?}",
+            floor_after
+        );
+        let rt60 = crate::analysis::rt60(&extended, sample_rate).unwrap();
+        assert!((rt60 - 500.0).abs() < 50.0, "rt60: {}", rt60);
+
+        for decay_rate in [0.0, 0.1, f32::NAN, f32::NEG_INFINITY] {
+            let floor = NoiseFloor {
+                decay_rate,
+                ..floor
+            };
+            let extended = extend_tail(&ir, &floor, 80.0, sample_rate, &mut rng);
+            assert_eq!(extended, None, "{}", decay_rate);
+        }
+    }
+
+    #[test]
+    fn test_resample() {
+        // 1 kHz tone survives, 10 kHz tone is above the new Nyquist
+        let tone = |frequency: f32, sample_rate: u32| -> Vec<f32> {
+            (0..sample_rate / 10)
+                .map(|i| {
+                    (2.0 * std::f32::consts::PI * frequency * i as f32
+                        / sample_rate as f32)
+                        .sin()
+                })
+                .collect()
+        };
+        let rms = |d: &[f32]| {
+            (d.iter().map(|x| x * x).sum::<f32>() / d.len() as f32).sqrt()
+        };
+
+        let result = resample(&tone(1000.0, 48000), 48000, 16000);
+        assert_eq!(result.len(), 1600);
+        let expected = tone(1000.0, 16000);
+        // Skip edges where the kernel runs out of samples
+        for (r, e) in result.iter().zip(&expected).skip(100).take(1400) {
+            assert!((r - e).abs() < 1e-2, "{} vs {}", r, e);
+        }
+
+        let aliased = resample(&tone(10000.0, 48000), 48000, 16000);
+        assert!(rms(&aliased[100..1500]) < 1e-2);
+    }
+
+    #[test]
+    fn test_add_fractional() {
+        let mut data = vec![0.0; 40];
+        add_fractional(&mut data, 20.0, 1.0);
+        assert_eq!(data[20], 1.0);
+        assert!(data
+            .iter()
+            .enumerate()
+            .all(|(i, &x)| i == 20 || x.abs() < 1e-6));
+
+        // Centroid of a delayed impulse sits at the fractional position
+        let mut data = vec![0.0; 40];
+        add_fractional(&mut data, 20.3, 1.0);
+        let centroid: f32 = data
+            .iter()
+            .enumerate()
+            .map(|(i, x)| i as f32 * x)
+            .sum::<f32>()
+            / data.iter().sum::<f32>();
+        assert!((centroid - 20.3).abs() < 0.05, "centroid: {}", centroid);
+        assert!((data.iter().sum::<f32>() - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_inverse_filter() {
+        let ir = [1.0, 0.0, 0.5, -0.25, 0.1, 0.0, 0.05];
+        let inverse = inverse_filter(&ir, 256, 1e-6);
+        let result = convolve(&ir, &inverse);
+        for (i, x) in result.iter().enumerate() {
+            let expected = if i == 128 { 1.0 } else { 0.0 };
+            assert!((x - expected).abs() < 1e-2, "{}: {}", i, x);
+        }
+    }
+
+    #[test]
+    fn test_ess_inverse() {
+        let sweep = ess(50.0, 7000.0, 500.0, 16000);
+        assert_eq!(sweep.len(), 8000);
+        assert!(sweep[0].abs() < 1e-6 && sweep[7999].abs() < 1e-6);
+
+        let inverse = ess_inverse(50.0, 7000.0, 500.0, 16000);
+        let result = convolve(&sweep, &inverse);
+        let peak = crate::analysis::peak_index(&result);
+        assert_eq!(peak, inverse.len() - 1);
+        assert!((result[peak] - 1.0).abs() < 1e-3, "peak: {}", result[peak]);
+        // Energy is compressed into a few samples around the peak
+        let near: f32 = result[peak - 8..=peak + 8].iter().map(|x| x * x).sum();
+        let total: f32 = result.iter().map(|x| x * x).sum();
+        assert!(near / total > 0.9, "ratio: {}", near / total);
+    }
+}
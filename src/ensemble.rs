@@ -0,0 +1,257 @@
+use ndarray_rand::rand::RngCore;
+
+use crate::analysis::{energy_decay_curve, peak_index};
+use crate::ImpulseResponseGenerator;
+
+/// Lowest decay curve level [dB] compared when picking the median
+/// realization, below it the curves are dominated by the last few samples
+const MEDIAN_EDC_FLOOR: f64 = -60.0;
+
+/// How the realizations of an ensemble are combined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnsembleMode {
+    /// Mean energy of every sample, a dense tail following the expected decay
+    /// with the direct to reverberant ratio of the realizations
+    #[default]
+    MeanEnergy,
+    /// Realization whose energy decay curve is closest to the median curve,
+    /// keeps the sparse structure of a single draw
+    MedianEdc,
+}
+
+impl EnsembleMode {
+    /// Name used on the command line and in WAV metadata
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnsembleMode::MeanEnergy => "mean-energy",
+            EnsembleMode::MedianEdc => "median-edc",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mean-energy" => Some(EnsembleMode::MeanEnergy),
+            "median-edc" => Some(EnsembleMode::MedianEdc),
+            _ => None,
+        }
+    }
+}
+
+/// Generator giving one representative impulse out of several stochastic
+/// realizations of the same parameters.
+///
+/// Reduces run-to-run variance when one impulse per condition is needed
+/// instead of random draws. All realizations come from the same rng one after
+/// another, a single realization is returned unchanged.
+#[derive(Debug, Clone)]
+pub struct Ensemble<G> {
+    generator: G,
+    realizations: usize,
+    mode: EnsembleMode,
+}
+
+impl<G> Ensemble<G> {
+    pub fn new(generator: G, realizations: usize) -> Self {
+        if realizations == 0 {
+            panic!("Ensemble needs at least one realization")
+        };
+        Self {
+            generator,
+            realizations,
+            mode: EnsembleMode::default(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: EnsembleMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+impl<G: ImpulseResponseGenerator> ImpulseResponseGenerator for Ensemble<G> {
+    fn generate_with_rng(
+        &self,
+        sample_rate: u32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<f32> {
+        let mut realizations: Vec<Vec<f32>> = (0..self.realizations)
+            .map(|_| self.generator.generate_with_rng(sample_rate, rng))
+            .collect();
+        if realizations.len() == 1 {
+            return realizations.remove(0);
+        }
+        match self.mode {
+            EnsembleMode::MeanEnergy => mean_energy(&realizations),
+            EnsembleMode::MedianEdc => {
+                let index = median_edc_index(&realizations);
+                realizations.swap_remove(index)
+            }
+        }
+    }
+}
+
+/// Mean energy per sample, as long as the longest realization. Samples after
+/// the direct sound are rescaled to the mean direct to reverberant ratio of
+/// the realizations [dB].
+fn mean_energy(realizations: &[Vec<f32>]) -> Vec<f32> {
+    let len = realizations.iter().map(Vec::len).max().unwrap_or(0);
+    let mut energy = vec![0.0f64; len];
+    for realization in realizations {
+        for (e, &x) in energy.iter_mut().zip(realization) {
+            *e += x as f64;
+        }
+    }
+    let count = realizations.len() as f64;
+    let mut mean: Vec<f32> = energy.iter().map(|e| (e / count) as f32).collect();
+    let drr = realizations.iter().map(|r| direct_ratio(r)).sum::<f64>() / count;
+    let excess = direct_ratio(&mean) - drr;
+    if excess.is_finite() {
+        let scale = 10f64.powf(excess / 10.0) as f32;
+        let peak = peak_index(&mean);
+        mean.iter_mut().skip(peak + 1).for_each(|x| *x *= scale);
+    }
+    mean
+}
+
+/// Peak sample to the sum of all later samples [dB]
+fn direct_ratio(ir: &[f32]) -> f64 {
+    let peak = peak_index(ir);
+    let direct = ir.get(peak).map_or(0.0, |&x| x as f64);
+    let reverberant = ir.iter().skip(peak + 1).map(|&x| x as f64).sum::<f64>();
+    10.0 * (direct / reverberant).log10()
+}
+
+/// Index of the realization with the smallest mean distance between its
+/// decay curve and the sample-wise median of all decay curves
+fn median_edc_index(realizations: &[Vec<f32>]) -> usize {
+    let len = realizations.iter().map(Vec::len).min().unwrap_or(0);
+    let curves: Vec<Vec<f64>> = realizations
+        .iter()
+        .map(|r| {
+            energy_decay_curve(&r[..len])
+                .into_iter()
+                .map(|level| level.max(MEDIAN_EDC_FLOOR))
+                .collect()
+        })
+        .collect();
+    let median: Vec<f64> = (0..len)
+        .map(|i| {
+            let mut levels: Vec<f64> = curves.iter().map(|c| c[i]).collect();
+            levels.sort_by(f64::total_cmp);
+            let middle = levels.len() / 2;
+            if levels.len().is_multiple_of(2) {
+                (levels[middle - 1] + levels[middle]) / 2.0
+            } else {
+                levels[middle]
+            }
+        })
+        .collect();
+    let distance = |curve: &Vec<f64>| {
+        curve
+            .iter()
+            .zip(&median)
+            .map(|(level, median)| (level - median).abs())
+            .sum::<f64>()
+    };
+    (0..curves.len())
+        .min_by(|&a, &b| distance(&curves[a]).total_cmp(&distance(&curves[b])))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::rt60;
+    use crate::verification::{Measurement, Tolerances};
+    use crate::{ImpulseResponseImproved, ImpulseResponseSimple, RirParams};
+    use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+    fn spread(values: &[f32]) -> f32 {
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        values.iter().map(|v| (v - mean).abs()).sum::<f32>() / values.len() as f32
+    }
+
+    #[test]
+    fn test_mean_energy_reduces_variance() {
+        let build = || ImpulseResponseImproved::new(500.0, 50.0, 4.0, 80.0, -3.0);
+        let (rir, ensemble) = (build(), Ensemble::new(build(), 8));
+        let measure = |generator: &dyn Fn(&mut StdRng) -> Vec<f32>| {
+            (0..6)
+                .map(|seed| {
+                    let ir = generator(&mut StdRng::seed_from_u64(seed));
+                    rt60(&ir, 16000).unwrap()
+                })
+                .collect::<Vec<f32>>()
+        };
+        let single = measure(&|rng| rir.generate_with_rng(16000, rng));
+        let averaged = measure(&|rng| ensemble.generate_with_rng(16000, rng));
+        assert!(spread(&averaged) < spread(&single));
+    }
+
+    #[test]
+    fn test_mean_energy_meets_targets() {
+        let params = RirParams {
+            rt60: 500.0,
+            edt: 50.0,
+            itdg: 4.0,
+            er_duration: 100.0,
+            drr: -3.0,
+        };
+        let rir = ImpulseResponseImproved::new(500.0, 50.0, 4.0, 100.0, -3.0);
+        let ensemble = Ensemble::new(rir, 4);
+        let tolerances = Tolerances {
+            rt60: 0.1,
+            edt: 0.1,
+            drr: 0.5,
+        };
+        for seed in 0..3 {
+            let ir =
+                ensemble.generate_with_rng(16000, &mut StdRng::seed_from_u64(seed));
+            let measured = Measurement::measure(&ir, 16000);
+            assert!(measured.within(&params, &tolerances), "{measured:?}");
+        }
+    }
+
+    #[test]
+    fn test_sparse_mean_energy_meets_targets() {
+        // Near 0 dB DRR leaves few early reflections, rarely coinciding
+        let params = RirParams {
+            rt60: 500.0,
+            edt: 50.0,
+            itdg: 4.0,
+            er_duration: 100.0,
+            drr: -0.9,
+        };
+        let rir = ImpulseResponseSimple::new(500.0, 50.0, 4.0, 100.0, -0.9);
+        let ensemble = Ensemble::new(rir, 4);
+        for seed in 0..10 {
+            let ir =
+                ensemble.generate_with_rng(44100, &mut StdRng::seed_from_u64(seed));
+            let measured = Measurement::measure(&ir, 44100);
+            assert!(
+                measured.within(&params, &Tolerances::default()),
+                "{measured:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_median_edc_picks_a_realization() {
+        let build = || ImpulseResponseImproved::new(500.0, 50.0, 4.0, 80.0, -3.0);
+        let rir = build();
+        let ensemble = Ensemble::new(build(), 5).with_mode(EnsembleMode::MedianEdc);
+        let picked =
+            ensemble.generate_with_rng(16000, &mut StdRng::seed_from_u64(1));
+        let mut rng = StdRng::seed_from_u64(1);
+        let realizations: Vec<Vec<f32>> = (0..5)
+            .map(|_| rir.generate_with_rng(16000, &mut rng))
+            .collect();
+        assert!(realizations.contains(&picked));
+
+        let single = Ensemble::new(build(), 1);
+        assert_eq!(
+            single.generate_with_rng(16000, &mut StdRng::seed_from_u64(2)),
+            rir.generate_with_rng(16000, &mut StdRng::seed_from_u64(2))
+        );
+    }
+}
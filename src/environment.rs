@@ -0,0 +1,135 @@
+/// Reference ambient pressure [kPa]
+const REFERENCE_PRESSURE: f64 = 101.325;
+/// Reference air temperature [K]
+const REFERENCE_TEMPERATURE: f64 = 293.15;
+/// Triple point isotherm temperature [K]
+const TRIPLE_POINT_TEMPERATURE: f64 = 273.16;
+
+/// Atmospheric conditions of the room.
+///
+/// Generation takes the speed of sound from it (`array` delays), and
+/// `ImpulseResponseImproved::with_environment` applies its ISO 9613-1 air
+/// absorption to the tail.
+///
+/// temperature: air temperature [°C]
+/// humidity: relative humidity [%]
+/// pressure: ambient atmospheric pressure [kPa]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Environment {
+    pub temperature: f32,
+    pub humidity: f32,
+    pub pressure: f32,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            temperature: 20.0,
+            humidity: 50.0,
+            pressure: 101.325,
+        }
+    }
+}
+
+impl Environment {
+    pub fn new(temperature: f32, humidity: f32, pressure: f32) -> Self {
+        if !temperature.is_finite() || temperature <= -273.15 {
+            panic!("Temperature should be finite and above -273.15 °C")
+        };
+        if !(0.0..=100.0).contains(&humidity) {
+            panic!("Relative humidity should be in range [0, 100] %")
+        };
+        if !pressure.is_finite() || pressure <= 0.0 {
+            panic!("Atmospheric pressure should be finite and positive")
+        };
+        Self {
+            temperature,
+            humidity,
+            pressure,
+        }
+    }
+
+    /// Speed of sound [m/s]
+    pub fn speed_of_sound(&self) -> f32 {
+        (331.3 * (1.0 + self.temperature as f64 / 273.15).sqrt()) as f32
+    }
+
+    /// Pure tone atmospheric absorption coefficient [dB/m], ISO 9613-1
+    pub fn absorption_coefficient(&self, frequency: f32) -> f32 {
+        let f = frequency as f64;
+        let t = self.temperature as f64 + 273.15;
+        let pa = self.pressure as f64 / REFERENCE_PRESSURE;
+        let t_rel = t / REFERENCE_TEMPERATURE;
+
+        // Molar concentration of water vapour [%]
+        let c = -6.8346 * (TRIPLE_POINT_TEMPERATURE / t).powf(1.261) + 4.6151;
+        let h = self.humidity as f64 * 10.0_f64.powf(c) / pa;
+
+        // Relaxation frequencies of oxygen and nitrogen [Hz]
+        let fr_o = pa * (24.0 + 4.04e4 * h * (0.02 + h) / (0.391 + h));
+        let fr_n = pa
+            * t_rel.powf(-0.5)
+            * (9.0 + 280.0 * h * (-4.170 * (t_rel.powf(-1.0 / 3.0) - 1.0)).exp());
+
+        let alpha = 8.686
+            * f.powi(2)
+            * (1.84e-11 / pa * t_rel.sqrt()
+                + t_rel.powf(-2.5)
+                    * (0.01275 * (-2239.1 / t).exp() / (fr_o + f.powi(2) / fr_o)
+                        + 0.1068 * (-3352.0 / t).exp()
+                            / (fr_n + f.powi(2) / fr_n)));
+        alpha as f32
+    }
+
+    /// Attenuation of a pure tone over the distance [dB]
+    pub fn attenuation(&self, frequency: f32, distance: f32) -> f32 {
+        self.absorption_coefficient(frequency) * distance
+    }
+
+    /// Attenuation of a pure tone after travelling for `time` [ms] [dB],
+    /// for the decaying tail of an impulse response
+    pub fn attenuation_after(&self, frequency: f32, time: f32) -> f32 {
+        self.attenuation(frequency, self.speed_of_sound() * time / 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_absorption_coefficient() {
+        // ISO 9613-1 table values at 20 °C, 70 % and 101.325 kPa [dB/km]
+        let environment = Environment::new(20.0, 70.0, 101.325);
+        let table =
+            vec![(500.0, 2.8), (1000.0, 5.0), (4000.0, 22.9), (8000.0, 76.6)];
+        for (frequency, expected) in table {
+            let result = environment.absorption_coefficient(frequency) * 1000.0;
+            assert!(
+                (result - expected).abs() / expected < 0.05,
+                "Frequency: {}, Expected: {}, Result: {}",
+                frequency,
+                expected,
+                result
+            );
+        }
+        assert!((environment.speed_of_sound() - 343.2).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_new_rejects_invalid_conditions() {
+        for (temperature, humidity, pressure) in [
+            (f32::NAN, 50.0, 101.325),
+            (-273.15, 50.0, 101.325),
+            (20.0, f32::NAN, 101.325),
+            (20.0, 120.0, 101.325),
+            (20.0, 50.0, f32::NAN),
+            (20.0, 50.0, 0.0),
+        ] {
+            let result = std::panic::catch_unwind(|| {
+                Environment::new(temperature, humidity, pressure)
+            });
+            assert!(result.is_err(), "{} {} {}", temperature, humidity, pressure);
+        }
+    }
+}
@@ -1,19 +1,42 @@
+use crate::analysis::{LATE_TAIL_START, OCTAVE_BANDS};
+use crate::common::{
+    is_supported_sample_rate, is_valid_duration, ms_to_samples, sum_f64,
+    MAX_SAMPLE_RATE, MIN_SAMPLE_RATE,
+};
+use crate::decay::DecayProfile;
+use crate::dsp::{add_fractional, diffuse, redistribute_tail};
+use crate::environment::Environment;
 use crate::{decibels_to_gain, ImpulseResponseGenerator};
 use ndarray::prelude::*;
 use ndarray_rand::{
-    rand::seq::SliceRandom, rand::thread_rng, rand_distr::Uniform, RandomExt,
+    rand::rngs::StdRng, rand::Rng, rand::RngCore, rand::SeedableRng,
+    rand_distr::Uniform, RandomExt,
 };
 use ndarray_stats::QuantileExt;
 use std::cmp::Ordering;
 use std::time::Duration;
 
-/// Stochastic impulse response.
+/// Length of the time strata of stratified thinning [ms]
+const THINNING_STRATUM: f32 = 5.0;
+/// Accepted DRR error of stratified thinning [dB]
+const STRATIFIED_DRR_TOLERANCE: f64 = 0.05;
+
+/// Stochastic impulse response, `ImpulseResponseSimple` with optional
+/// clarity, crest factor and decay shape controls.
 ///
 /// rt60: reverberation time [ms]
 /// edt: early decay time [ms]
 /// itdg: initial time delay gap [ms]
 /// er_duration: early reflections duration [ms]
 /// drr: direct to reverberant energy ratio [dB]
+/// clarity: optional early to late energy ratio target, see `with_clarity`
+/// crest_factor: optional late tail crest factor, see `with_crest_factor`
+/// decay: optional decay curve replacing EDT/RT60 slopes, see `with_decay`
+/// thinning: how reflections are picked to reach DRR, see `with_thinning`
+/// fractional_delay: early reflections off the sample grid, see
+/// `with_fractional_delay`
+/// diffusion: optional allpass diffusion of the late tail, see `with_diffusion`
+/// environment: optional air absorption of the tail, see `with_environment`
 #[derive(Debug)]
 pub struct ImpulseResponseImproved {
     rt60: f32,
@@ -21,27 +44,132 @@ pub struct ImpulseResponseImproved {
     itdg: f32,
     er_duration: f32,
     drr: f32,
+    clarity: Option<Clarity>,
+    crest_factor: Option<f32>,
+    decay: Option<DecayProfile>,
+    thinning: Thinning,
+    fractional_delay: bool,
+    diffusion: Option<f32>,
+    environment: Option<Environment>,
+}
+
+/// Selection of the reflections removed while steering DRR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Thinning {
+    /// Uniformly random subset of the rays, fixed steps land anywhere within
+    /// 0.5 dB of the target
+    #[default]
+    Uniform,
+    /// Rays are removed until the share of energy of every `THINNING_STRATUM`
+    /// is gone and the last step removes just the energy above the target,
+    /// DRR ends up within `STRATIFIED_DRR_TOLERANCE` of the target unless only
+    /// a few strong early rays are left to remove (DRR near 0 dB)
+    Stratified,
+}
+
+/// Clarity, early to late energy ratio [dB] with the early part ending 50 ms
+/// (speech) or 80 ms (music) after the direct sound
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clarity {
+    C50(f32),
+    C80(f32),
+}
+
+impl Clarity {
+    /// Length of the early part [ms]
+    pub fn time(&self) -> f32 {
+        match self {
+            Clarity::C50(_) => 50.0,
+            Clarity::C80(_) => 80.0,
+        }
+    }
+
+    /// Target value [dB]
+    pub fn value(&self) -> f32 {
+        match self {
+            Clarity::C50(value) | Clarity::C80(value) => *value,
+        }
+    }
 }
 
 impl ImpulseResponseGenerator for ImpulseResponseImproved {
-    /// Generate impulse response
-    fn generate(&self, sample_rate: u32) -> Vec<f32> {
-        let mut noise = self.get_noise(sample_rate);
+    /// Generate impulse response using given random number generator
+    fn generate_with_rng(
+        &self,
+        sample_rate: u32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<f32> {
+        if !is_supported_sample_rate(sample_rate) {
+            panic!(
+                "Sample rate {} is out of supported range [{}, {}]",
+                sample_rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+            )
+        };
+        // Thinning order is drawn per sample from its own stream, impulses
+        // with the same seed and different lengths (see `morph`) thin out
+        // the same positions first
+        let mut order_rng = StdRng::seed_from_u64(rng.gen());
+        let mut noise = self.get_noise(sample_rate, rng);
+        let thinning_order: Vec<f32> =
+            (0..noise.len()).map(|_| order_rng.gen()).collect();
         let (dsi, ersi, erei) =
             self.get_edt_and_rt60_slope(&mut noise, sample_rate);
-        self.randomize_reflections(&mut noise, dsi, ersi, erei, sample_rate);
-        noise.into_raw_vec()[dsi..].to_vec()
+        // Before thinning, so DRR is still steered to its target
+        if let Some(environment) = &self.environment {
+            Self::absorb_in_air(&mut noise, dsi, environment, sample_rate);
+        }
+        self.randomize_reflections(
+            &mut noise,
+            dsi,
+            ersi,
+            erei,
+            sample_rate,
+            &thinning_order,
+        );
+        if self.fractional_delay {
+            let itdg = self.itdg as f64 * sample_rate as f64 / 1000.0;
+            Self::place_fractional(&mut noise, dsi, erei, itdg, rng);
+        }
+        let late_tail_start = dsi + ms_to_samples(LATE_TAIL_START, sample_rate);
+        if let Some(crest_factor) = self.crest_factor {
+            redistribute_tail(
+                noise.as_slice_mut().unwrap(),
+                late_tail_start,
+                crest_factor,
+                sample_rate,
+            );
+        }
+        if let Some(diffusion) = self.diffusion {
+            diffuse(
+                noise.as_slice_mut().unwrap(),
+                late_tail_start,
+                diffusion,
+                sample_rate,
+            );
+        }
+        // Drop samples before the direct sound in place, the noise buffer
+        // becomes the output without another allocation
+        let mut impulse = noise.into_raw_vec();
+        impulse.drain(..dsi);
+        impulse
     }
 }
 
 impl ImpulseResponseImproved {
     /// Random noize (white)
-    fn get_noise(&self, sample_rate: u32) -> Array1<f32> {
-        let num_samples = Self::get_num_samples(
-            Duration::from_millis(self.rt60.round() as u64),
-            sample_rate,
-        );
-        Array1::random(num_samples as usize, Uniform::new(-5.0, 5.0))
+    fn get_noise<R: Rng + ?Sized>(
+        &self,
+        sample_rate: u32,
+        rng: &mut R,
+    ) -> Array1<f32> {
+        let num_samples =
+            Self::get_num_samples(Self::ms_to_duration(self.rt60), sample_rate);
+        // At least the direct sound and a single reflection
+        Array1::random_using(
+            num_samples.max(2) as usize,
+            Uniform::new(-5.0, 5.0),
+            rng,
+        )
     }
 
     fn get_edt_and_rt60_slope(
@@ -49,32 +177,25 @@ impl ImpulseResponseImproved {
         data: &mut Array1<f32>,
         sample_rate: u32,
     ) -> (usize, usize, usize) {
-        let edt_num_samples = Self::get_num_samples(
-            Duration::from_millis(self.edt.round() as u64),
-            sample_rate,
-        );
-        let rt60_num_samples = Self::get_num_samples(
-            Duration::from_millis(self.rt60.round() as u64),
-            sample_rate,
-        );
+        // Short EDT may map to zero samples at low sample rates
+        let edt_num_samples =
+            Self::get_num_samples(Self::ms_to_duration(self.edt), sample_rate)
+                .max(1);
         let er_duration_num_samples = Self::get_num_samples(
-            Duration::from_millis(self.er_duration.round() as u64),
+            Self::ms_to_duration(self.er_duration),
             sample_rate,
         );
 
-        // Shape the EDT slope of the IR
-        for i in 0..(edt_num_samples - 1) as usize {
-            data[i] -= i as f32;
-        }
-        for i in (edt_num_samples - 1) as usize..data.len() {
-            data[i] -= (edt_num_samples - 1) as f32;
-        }
+        // Shape the decay of the IR, EDT and RT60 slopes unless a custom
+        // profile is given, time is computed in f64 since sample indices of
+        // long tails are not exactly representable in f32
+        let decay = self
+            .decay
+            .clone()
+            .unwrap_or_else(|| DecayProfile::edt_rt60(self.edt, self.rt60));
         *data *= 10.0 / edt_num_samples as f32;
-
-        // Shape the RT60 slope of the IR (after EDT)
-        for i in edt_num_samples..rt60_num_samples {
-            data[i as usize] -= (i as f32 - (edt_num_samples + 1) as f32) * 50.0
-                / rt60_num_samples as f32;
+        for (i, x) in data.iter_mut().enumerate() {
+            *x += decay.level((i as f64 * 1000.0 / sample_rate as f64) as f32);
         }
 
         // Change scale to dBFS (0 dB becomes the maximal level)
@@ -101,6 +222,36 @@ impl ImpulseResponseImproved {
         (direct_sound_idx, er_start_idx, er_end_idx)
     }
 
+    /// Attenuate every sample after the direct sound by the air absorption
+    /// of its extra travel time, mean energy of the octave bands below
+    /// Nyquist, so the tail decays faster than the requested slopes the more
+    /// high frequencies the sample rate carries
+    fn absorb_in_air(
+        data: &mut Array1<f32>,
+        direct_sound_idx: usize,
+        environment: &Environment,
+        sample_rate: u32,
+    ) {
+        let nyquist = sample_rate as f32 / 2.0;
+        let sample_time = 1000.0 / sample_rate as f32;
+        // Attenuation of every band per sample [dB]
+        let rates: Vec<f64> = OCTAVE_BANDS
+            .iter()
+            .filter(|&&center| center * std::f32::consts::SQRT_2 <= nyquist)
+            .map(|&center| {
+                environment.attenuation_after(center, sample_time) as f64
+            })
+            .collect();
+        for (i, x) in data.iter_mut().skip(direct_sound_idx + 1).enumerate() {
+            let samples = (i + 1) as f64;
+            let energy: f64 = rates
+                .iter()
+                .map(|rate| 10.0_f64.powf(-rate * samples / 10.0))
+                .sum();
+            *x *= (energy / rates.len() as f64) as f32;
+        }
+    }
+
     fn randomize_reflections(
         &self,
         data: &mut Array1<f32>,
@@ -108,11 +259,28 @@ impl ImpulseResponseImproved {
         early_ref_start: usize,
         early_ref_end: usize,
         sample_rate: u32,
+        thinning_order: &[f32],
     ) {
         self.create_initial_time_delay_gap(data, direct_sound_idx, sample_rate);
 
-        let drr_low = self.drr - 0.5;
-        let drr_high = self.drr + 0.5;
+        // Steer clarity first, thinning out for DRR below removes rays from
+        // early and late parts alike, so it keeps clarity roughly in place
+        if let Some(clarity) = self.clarity {
+            Self::steer_clarity(
+                data,
+                direct_sound_idx,
+                clarity,
+                sample_rate,
+                thinning_order,
+            );
+        }
+
+        let tolerance = match self.thinning {
+            Thinning::Uniform => 0.5,
+            Thinning::Stratified => STRATIFIED_DRR_TOLERANCE,
+        };
+        let drr_low = self.drr as f64 - tolerance;
+        let drr_high = self.drr as f64 + tolerance;
 
         let mut current_drr =
             Self::calculate_drr_energy_ratio(data, direct_sound_idx);
@@ -122,94 +290,292 @@ impl ImpulseResponseImproved {
         }
 
         while drr_low > current_drr {
-            // Thin out early reflections
-            Self::thin_out_reflections(
-                data,
-                early_ref_start,
-                early_ref_end,
-                1.0 / 8.0,
-            );
+            if self.thinning == Thinning::Stratified {
+                self.thin_out_stratified_step(
+                    data,
+                    direct_sound_idx,
+                    early_ref_start,
+                    early_ref_end,
+                    sample_rate,
+                    thinning_order,
+                );
+            } else {
+                // Thin out early reflections
+                Self::thin_out_reflections(
+                    data,
+                    early_ref_start,
+                    early_ref_end,
+                    1.0 / 8.0,
+                    thinning_order,
+                );
 
-            // Thin out reverberation tail
-            Self::thin_out_reflections(
-                data,
-                early_ref_end,
-                data.len() - 1,
-                1.0 / 10.0,
-            );
+                // Thin out reverberation tail
+                Self::thin_out_reflections(
+                    data,
+                    early_ref_end,
+                    data.len() - 1,
+                    1.0 / 10.0,
+                    thinning_order,
+                );
+            }
 
             let previous_drr = current_drr;
             current_drr = Self::calculate_drr_energy_ratio(data, direct_sound_idx);
 
             // If thinning out reflections did not decrease the DRR, it means
             // that the maximal DRR possible has been reached
-            if (previous_drr - current_drr).abs() < std::f32::EPSILON {
+            if (previous_drr - current_drr).abs() < f64::EPSILON {
                 break;
             }
         }
     }
 
-    fn create_initial_time_delay_gap(
+    /// Thin out early reflections and the tail like the uniform step, by
+    /// energy instead of ray count, scaled down when less than a full step
+    /// separates DRR from the target
+    fn thin_out_stratified_step(
         &self,
         data: &mut Array1<f32>,
         direct_sound_idx: usize,
+        early_ref_start: usize,
+        early_ref_end: usize,
         sample_rate: u32,
+        thinning_order: &[f32],
     ) {
-        let itdg_num_samples = Self::get_num_samples(
-            Duration::from_millis(self.itdg.round() as u64),
+        let (mut early_rate, mut tail_rate) = (1.0 / 8.0, 1.0 / 10.0);
+        let early = sum_f64(data.slice(s![early_ref_start..early_ref_end]));
+        let tail = sum_f64(data.slice(s![early_ref_end..]));
+        let target =
+            data[direct_sound_idx] as f64 / 10.0_f64.powf(self.drr as f64 / 10.0);
+        let excess = early + tail - target;
+        let step = early * early_rate + tail * tail_rate;
+        if excess < step {
+            let scale = excess / step;
+            early_rate *= scale;
+            tail_rate *= scale;
+        }
+
+        Self::thin_out_stratified(
+            data,
+            early_ref_start,
+            early_ref_end - 1,
+            early_rate,
+            sample_rate,
+            thinning_order,
+        );
+        Self::thin_out_stratified(
+            data,
+            early_ref_end,
+            data.len() - 1,
+            tail_rate,
             sample_rate,
+            thinning_order,
         );
+    }
+
+    /// Remove rays in `thinning_order` until `rate` of the energy of every
+    /// stratum between the indices is gone, rays which would overshoot the
+    /// share by more than they fill it are skipped and what is left over
+    /// carries to the next stratum, so sparse strata are thinned out too
+    fn thin_out_stratified(
+        data: &mut Array1<f32>,
+        start_idx: usize,
+        end_idx: usize,
+        rate: f64,
+        sample_rate: u32,
+        thinning_order: &[f32],
+    ) {
+        let stratum = ms_to_samples(THINNING_STRATUM, sample_rate).max(1);
+        let mut stratum_start = start_idx;
+        let mut remaining = 0.0;
+        while stratum_start <= end_idx {
+            let stratum_end = (stratum_start + stratum - 1).min(end_idx);
+            let mut ray_indices: Vec<usize> = (stratum_start..=stratum_end)
+                .filter(|&idx| data[idx] != 0.0)
+                .collect();
+            ray_indices.sort_unstable_by(|&a, &b| {
+                thinning_order[a].total_cmp(&thinning_order[b])
+            });
+            remaining += rate * sum_f64(ray_indices.iter().map(|&idx| &data[idx]));
+            for index in ray_indices {
+                if remaining <= 0.0 {
+                    break;
+                }
+                let energy = data[index] as f64;
+                if energy < 2.0 * remaining {
+                    remaining -= energy;
+                    data[index] = 0.0;
+                }
+            }
+            stratum_start = stratum_end + 1;
+        }
+    }
+
+    fn steer_clarity(
+        data: &mut Array1<f32>,
+        direct_sound_idx: usize,
+        clarity: Clarity,
+        sample_rate: u32,
+        thinning_order: &[f32],
+    ) {
+        let split_idx = (direct_sound_idx
+            + Self::get_num_samples(
+                Self::ms_to_duration(clarity.time()),
+                sample_rate,
+            ) as usize)
+            .min(data.len() - 1);
+        let clarity_low = clarity.value() as f64 - 0.5;
+        let clarity_high = clarity.value() as f64 + 0.5;
+
+        let mut current_clarity =
+            Self::calculate_clarity(data, direct_sound_idx, split_idx);
+
+        while current_clarity < clarity_low || current_clarity > clarity_high {
+            if current_clarity < clarity_low {
+                // Too much late energy, thin out the late part
+                Self::thin_out_reflections(
+                    data,
+                    split_idx,
+                    data.len() - 1,
+                    1.0 / 10.0,
+                    thinning_order,
+                );
+            } else {
+                // Too much early energy, thin out early reflections
+                Self::thin_out_reflections(
+                    data,
+                    direct_sound_idx + 1,
+                    split_idx - 1,
+                    1.0 / 10.0,
+                    thinning_order,
+                );
+            }
+
+            let previous_clarity = current_clarity;
+            current_clarity =
+                Self::calculate_clarity(data, direct_sound_idx, split_idx);
+
+            // Nothing left to thin out in the part which needs it
+            if (previous_clarity - current_clarity).abs() < f64::EPSILON {
+                break;
+            }
+        }
+    }
+
+    fn calculate_clarity(
+        data: &Array1<f32>,
+        direct_sound_idx: usize,
+        split_idx: usize,
+    ) -> f64 {
+        let early = sum_f64(data.slice(s![direct_sound_idx..split_idx]));
+        let late = sum_f64(data.slice(s![split_idx..]));
+        10.0 * ((early / late).log10())
+    }
+
+    fn create_initial_time_delay_gap(
+        &self,
+        data: &mut Array1<f32>,
+        direct_sound_idx: usize,
+        sample_rate: u32,
+    ) {
+        let itdg_num_samples =
+            Self::get_num_samples(Self::ms_to_duration(self.itdg), sample_rate);
         let itdg_end_idx = usize::min(
             direct_sound_idx + 1 + itdg_num_samples as usize,
             data.len() - 1,
         );
-        for elem in data
-            .slice_mut(s![direct_sound_idx + 1..itdg_end_idx])
-            .iter_mut()
-        {
+        let itdg_start_idx = (direct_sound_idx + 1).min(itdg_end_idx);
+        for elem in data.slice_mut(s![itdg_start_idx..itdg_end_idx]).iter_mut() {
             *elem = 0.0;
         }
     }
 
+    /// Move early reflections off the sample grid, the first one lands
+    /// exactly `itdg` [samples] after the direct sound (which stays the time
+    /// origin) and the others get random sub-sample offsets
+    fn place_fractional<R: Rng + ?Sized>(
+        data: &mut Array1<f32>,
+        direct_sound_idx: usize,
+        early_ref_end: usize,
+        itdg: f64,
+        rng: &mut R,
+    ) {
+        // Integer gap is rounded, first ray after it moves by the remainder
+        let shift = itdg - itdg.round();
+        let rays: Vec<(usize, f32)> = (direct_sound_idx + 1..=early_ref_end)
+            .filter(|&idx| data[idx] != 0.0)
+            .map(|idx| (idx, data[idx]))
+            .collect();
+        let data = data.as_slice_mut().unwrap();
+        for &(idx, _) in &rays {
+            data[idx] = 0.0;
+        }
+
+        let mut first_position = None;
+        for (idx, value) in rays {
+            let position = idx as f64 + shift;
+            let position = match first_position {
+                None => *first_position.insert(position),
+                Some(first) => (position + rng.gen_range(-0.5..0.5)).max(first),
+            };
+            add_fractional(data, position, value);
+        }
+    }
+
+    /// DRR of the impulse as it is returned, the direct sound is the peak
+    /// sample alone, the louder samples before it get drained in `generate`
     fn calculate_drr_energy_ratio(
         data: &Array1<f32>,
         direct_sound_idx: usize,
-    ) -> f32 {
-        let direct = data.slice(s![..=direct_sound_idx]).sum();
-        let reverberant = data.slice(s![direct_sound_idx + 1..]).sum();
+    ) -> f64 {
+        let direct = data[direct_sound_idx] as f64;
+        let reverberant = sum_f64(data.slice(s![direct_sound_idx + 1..]));
         10.0 * ((direct / reverberant).log10())
     }
 
+    /// Remove `rate` of the rays between the indices, rays come first in
+    /// `thinning_order` (random per sample) leave first
     fn thin_out_reflections(
         data: &mut Array1<f32>,
         start_idx: usize,
         end_idx: usize,
         rate: f32,
+        thinning_order: &[f32],
     ) {
-        let ray_indices: Vec<usize> = (start_idx..=end_idx)
+        let mut ray_indices: Vec<usize> = (start_idx..=end_idx)
             .filter(|&idx| data[idx] != 0.0)
             .collect();
         let num_rays = ((ray_indices.len() as f32) * rate).round() as usize;
 
-        // assert!(num_rays >= 1);
+        // First rays of a random order are a uniformly random subset
         if num_rays >= 1 {
-            let mut rng = thread_rng();
-            let random_subset: Vec<usize> = ray_indices
-                .choose_multiple(&mut rng, num_rays)
-                .cloned()
-                .collect();
-
-            for &index in random_subset.iter() {
+            ray_indices.select_nth_unstable_by(num_rays - 1, |&a, &b| {
+                thinning_order[a].total_cmp(&thinning_order[b])
+            });
+            for &index in &ray_indices[..num_rays] {
                 data[index] = 0.0;
             }
         };
     }
 
     fn get_num_samples(t: Duration, sample_rate: u32) -> u32 {
-        (t.as_secs_f32() * sample_rate as f32).round() as u32
+        (t.as_secs_f64() * sample_rate as f64).round() as u32
+    }
+
+    /// Fractional milliseconds to duration, keeps sub-millisecond precision,
+    /// negative and NaN times become zero
+    fn ms_to_duration(ms: f32) -> Duration {
+        Duration::try_from_secs_f64(ms.max(0.0) as f64 / 1000.0)
+            .unwrap_or(Duration::ZERO)
     }
 
     pub fn new(rt60: f32, edt: f32, itdg: f32, er_duration: f32, drr: f32) -> Self {
+        if ![rt60, edt, itdg, er_duration]
+            .into_iter()
+            .all(is_valid_duration)
+        {
+            panic!("Times (rt60, edt, itdg, er_duration) should be finite and non-negative")
+        };
         if rt60 <= edt {
             panic!("Reverb time (rt60) can't be lower than Early decay time (edt)")
         };
@@ -219,8 +585,70 @@ impl ImpulseResponseImproved {
             itdg,
             er_duration,
             drr,
+            clarity: None,
+            crest_factor: None,
+            decay: None,
+            thinning: Thinning::default(),
+            fractional_delay: false,
+            diffusion: None,
+            environment: None,
         }
     }
+
+    /// Absorb the tail in the air of the room (ISO 9613-1), broadband over
+    /// the octave bands the sample rate carries, on top of the EDT/RT60 decay
+    pub fn with_environment(mut self, environment: Environment) -> Self {
+        self.environment = Some(environment);
+        self
+    }
+
+    /// Run the late tail (from `LATE_TAIL_START`) through allpass filters,
+    /// diffusion in [0, 1], denser and smoother tails with the same decay,
+    /// helps grainy short RT60 tails
+    pub fn with_diffusion(mut self, diffusion: f32) -> Self {
+        if !(0.0..=1.0).contains(&diffusion) {
+            panic!("Diffusion should be in range [0, 1]")
+        };
+        self.diffusion = Some(diffusion);
+        self
+    }
+
+    /// Place early reflections at fractional sample positions with a windowed
+    /// sinc kernel, so ITDG and the early pattern aren't quantized to whole
+    /// samples at low sample rates, the kernel rings into the gap a little
+    pub fn with_fractional_delay(mut self) -> Self {
+        self.fractional_delay = true;
+        self
+    }
+
+    /// Pick reflections to thin out for DRR, see `Thinning`
+    pub fn with_thinning(mut self, thinning: Thinning) -> Self {
+        self.thinning = thinning;
+        self
+    }
+
+    /// Smooth the late tail (from `LATE_TAIL_START`) down to target crest
+    /// factor, thinned tails are spiky while measured late fields are near
+    /// Gaussian (about 4 for a second of noise), check the result with
+    /// `analysis::tail_statistics`
+    pub fn with_crest_factor(mut self, crest_factor: f32) -> Self {
+        self.crest_factor = Some(crest_factor);
+        self
+    }
+
+    /// Shape the response with the given decay curve instead of EDT/RT60
+    /// slopes, rt60 still sets the length and DRR/ITDG work as usual
+    pub fn with_decay(mut self, decay: DecayProfile) -> Self {
+        self.decay = Some(decay);
+        self
+    }
+
+    /// Steer reflections towards target C50 or C80 in addition to DRR, to
+    /// target clarity only pass `f32::NEG_INFINITY` as drr
+    pub fn with_clarity(mut self, clarity: Clarity) -> Self {
+        self.clarity = Some(clarity);
+        self
+    }
 }
 
 #[cfg(test)]
@@ -240,4 +668,240 @@ mod tests {
         }
         assert!(non_zero_elements > 0);
     }
+
+    #[test]
+    fn test_sub_millisecond_resolution() {
+        let itdg = ImpulseResponseImproved::ms_to_duration(0.5);
+        assert_eq!(ImpulseResponseImproved::get_num_samples(itdg, 48000), 24);
+        let edt = ImpulseResponseImproved::ms_to_duration(2.25);
+        assert_eq!(ImpulseResponseImproved::get_num_samples(edt, 16000), 36);
+    }
+
+    #[test]
+    fn test_invalid_times() {
+        assert_eq!(
+            ImpulseResponseImproved::ms_to_duration(-1.0),
+            Duration::ZERO
+        );
+        assert_eq!(
+            ImpulseResponseImproved::ms_to_duration(f32::NAN),
+            Duration::ZERO
+        );
+        for itdg in [-1.0, f32::NAN] {
+            let result = std::panic::catch_unwind(|| {
+                ImpulseResponseImproved::new(500.0, 50.0, itdg, 50.0, -1.0)
+            });
+            assert!(result.is_err(), "itdg: {}", itdg);
+        }
+    }
+
+    #[test]
+    fn test_supported_sample_rates() {
+        // edt and itdg map to zero samples at 8 kHz
+        let rir = ImpulseResponseImproved::new(500.0, 0.05, 0.05, 50.0, -1.0);
+        for sample_rate in [8000, 16000, 44100, 96000, 192000] {
+            assert!(!rir.generate(sample_rate).is_empty());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_unsupported_sample_rate() {
+        let rir = ImpulseResponseImproved::new(500.0, 50.0, 5.0, 50.0, -1.0);
+        rir.generate(4000);
+    }
+
+    #[test]
+    fn test_air_absorption() {
+        use crate::verification::Measurement;
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        let rir = ImpulseResponseImproved::new(2000.0, 100.0, 5.0, 80.0, -3.0);
+        let dry_air = Environment::new(20.0, 10.0, 101.325);
+        let absorbed = ImpulseResponseImproved::new(2000.0, 100.0, 5.0, 80.0, -3.0)
+            .with_environment(dry_air);
+        let measure = |rir: &ImpulseResponseImproved| {
+            let impulse =
+                rir.generate_with_rng(48000, &mut StdRng::seed_from_u64(6));
+            Measurement::measure(&impulse, 48000)
+        };
+        let (plain, absorbed) = (measure(&rir), measure(&absorbed));
+        assert!(
+            absorbed.rt60.unwrap() < 0.97 * plain.rt60.unwrap(),
+            "{:?} vs {:?}",
+            absorbed,
+            plain
+        );
+        assert!((absorbed.drr + 3.0).abs() <= 1.0, "{:?}", absorbed);
+    }
+
+    #[test]
+    fn test_generation_with_seeded_rng() {
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        let rir = ImpulseResponseImproved::new(500.0, 50.0, 5.0, 50.0, -1.0);
+        let first = rir.generate_with_rng(16000, &mut StdRng::seed_from_u64(42));
+        let second = rir.generate_with_rng(16000, &mut StdRng::seed_from_u64(42));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_clarity_target() {
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        let sample_rate = 16000;
+        let split = 50 * sample_rate as usize / 1000;
+        for target in [5.0, 15.0] {
+            let rir = ImpulseResponseImproved::new(
+                800.0,
+                50.0,
+                2.0,
+                50.0,
+                f32::NEG_INFINITY,
+            )
+            .with_clarity(Clarity::C50(target));
+            let impulse =
+                rir.generate_with_rng(sample_rate, &mut StdRng::seed_from_u64(7));
+            let early = sum_f64(&impulse[..split]);
+            let late = sum_f64(&impulse[split..]);
+            let c50 = 10.0 * (early / late).log10();
+            assert!((c50 - target as f64).abs() <= 0.5, "c50: {}", c50);
+        }
+    }
+
+    #[test]
+    fn test_custom_decay() {
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        // Flat for 100 ms, then 40 dB lower
+        let sample_rate = 16000;
+        let rir =
+            ImpulseResponseImproved::new(500.0, 50.0, 0.0, 50.0, f32::NEG_INFINITY)
+                .with_decay(DecayProfile::custom(|t| {
+                    if t < 100.0 {
+                        0.0
+                    } else {
+                        -40.0
+                    }
+                }));
+        let impulse =
+            rir.generate_with_rng(sample_rate, &mut StdRng::seed_from_u64(5));
+        let split = impulse.len() - 400 * sample_rate as usize / 1000;
+        let mean = |d: &[f32]| sum_f64(d) / d.len() as f64;
+        let ratio = mean(&impulse[split..]) / mean(&impulse[..split]);
+        assert!(ratio > 1e-5 && ratio < 1e-3, "ratio: {}", ratio);
+    }
+
+    #[test]
+    fn test_stratified_thinning() {
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        let target = -12.0;
+        let spread = |thinning: Thinning| {
+            let rir = ImpulseResponseImproved::new(800.0, 50.0, 4.0, 80.0, target)
+                .with_thinning(thinning);
+            let errors: Vec<f64> = (0..20)
+                .map(|seed| {
+                    let impulse = rir
+                        .generate_with_rng(16000, &mut StdRng::seed_from_u64(seed));
+                    let drr =
+                        10.0 * (impulse[0] as f64 / sum_f64(&impulse[1..])).log10();
+                    (drr - target as f64).abs()
+                })
+                .collect();
+            errors.iter().cloned().fold(0.0, f64::max)
+        };
+        let uniform = spread(Thinning::Uniform);
+        let stratified = spread(Thinning::Stratified);
+        assert!(stratified <= 0.05, "stratified: {}", stratified);
+        assert!(stratified < uniform, "{} vs {}", stratified, uniform);
+    }
+
+    #[test]
+    fn test_fractional_placement() {
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        // Direct sound and a single ray right after a 2.3 ms gap at 8 kHz
+        let itdg = 2.3 * 8000.0 / 1000.0;
+        let mut data = Array1::zeros(64);
+        data[0] = 1.0;
+        data[19] = 0.5;
+        ImpulseResponseImproved::place_fractional(
+            &mut data,
+            0,
+            40,
+            itdg,
+            &mut StdRng::seed_from_u64(1),
+        );
+        assert_eq!(data[0], 1.0);
+        let ray = data.slice(s![1..]);
+        let centroid = ray
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (i + 1) as f32 * x)
+            .sum::<f32>()
+            / ray.sum();
+        assert!((centroid - 19.4).abs() < 0.05, "centroid: {}", centroid);
+
+        let rir = ImpulseResponseImproved::new(500.0, 50.0, 2.3, 50.0, -3.0)
+            .with_fractional_delay();
+        let impulse = rir.generate_with_rng(8000, &mut StdRng::seed_from_u64(2));
+        assert!(impulse.iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn test_diffusion() {
+        use crate::analysis::rt60;
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        let rir = ImpulseResponseImproved::new(300.0, 30.0, 4.0, 50.0, -3.0);
+        let plain = rir.generate_with_rng(16000, &mut StdRng::seed_from_u64(4));
+        let diffused = rir
+            .with_diffusion(1.0)
+            .generate_with_rng(16000, &mut StdRng::seed_from_u64(4));
+        let (plain, diffused) = (
+            rt60(&plain, 16000).unwrap(),
+            rt60(&diffused, 16000).unwrap(),
+        );
+        assert!(
+            (diffused - plain).abs() / plain < 0.1,
+            "{} vs {}",
+            diffused,
+            plain
+        );
+    }
+
+    #[test]
+    fn test_segment() {
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        let rir = ImpulseResponseImproved::new(500.0, 50.0, 5.0, 50.0, -3.0);
+        let full = rir.generate_with_rng(16000, &mut StdRng::seed_from_u64(8));
+        let late = rir.generate_segment_with_rng(
+            16000,
+            80.0,
+            f32::INFINITY,
+            &mut StdRng::seed_from_u64(8),
+        );
+        assert_eq!(late, full[1280..]);
+        let early = rir.generate_segment_with_rng(
+            16000,
+            0.0,
+            50.0,
+            &mut StdRng::seed_from_u64(8),
+        );
+        assert_eq!(early, full[..800]);
+    }
+
+    #[test]
+    fn test_crest_factor_target() {
+        use crate::analysis::tail_statistics;
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        let rir = ImpulseResponseImproved::new(1000.0, 50.0, 5.0, 50.0, -3.0)
+            .with_crest_factor(4.5);
+        let impulse = rir.generate_with_rng(16000, &mut StdRng::seed_from_u64(11));
+        let statistics = tail_statistics(&impulse, 16000).unwrap();
+        assert!(statistics.crest_factor <= 4.5, "{:?}", statistics);
+    }
 }
@@ -1,11 +1,124 @@
+pub mod analysis;
+pub mod array;
 pub mod common;
+pub mod decay;
+pub mod dsp;
+pub mod ensemble;
+pub mod environment;
 pub mod improved;
+pub mod mat;
+pub mod matched;
+pub mod morph;
+pub mod multichannel;
+pub mod params;
 pub mod simple;
+pub mod sparse;
+pub mod verification;
+#[cfg(feature = "wav")]
+pub mod wav;
 
 pub use common::decibels_to_gain;
-pub use improved::ImpulseResponseImproved;
+pub use decay::DecayProfile;
+pub use environment::Environment;
+pub use improved::{Clarity, ImpulseResponseImproved, Thinning};
+pub use params::{ParamRanges, RirParams};
 pub use simple::ImpulseResponseSimple;
 
+pub use ndarray_rand::rand;
+use rand::RngCore;
+
+/// Impulse response generator, object safe so that different generators can
+/// be kept as `Box<dyn ImpulseResponseGenerator>`.
 pub trait ImpulseResponseGenerator {
-    fn generate(&self, sample_rate: u32) -> Vec<f32>;
+    /// Generate impulse response with a fresh thread rng
+    fn generate(&self, sample_rate: u32) -> Vec<f32> {
+        self.generate_with_rng(sample_rate, &mut rand::thread_rng())
+    }
+
+    /// Same as `generate`, but all randomness comes from the given generator,
+    /// so seeded generators give reproducible impulses.
+    fn generate_with_rng(
+        &self,
+        sample_rate: u32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<f32>;
+
+    /// Samples between `start` and `end` [ms] after the direct sound, the
+    /// same ones `generate_with_rng` gives for the same rng state, e.g. only
+    /// the late field after 80 ms to join a measured early part.
+    ///
+    /// DRR and clarity steering depend on the whole response, so it is still
+    /// generated in full, `end` past the response length is clamped.
+    fn generate_segment_with_rng(
+        &self,
+        sample_rate: u32,
+        start: f32,
+        end: f32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<f32> {
+        if !(0.0..=end).contains(&start) {
+            panic!("Segment should start at or after 0 ms and before its end")
+        };
+        let mut impulse = self.generate_with_rng(sample_rate, rng);
+        let end = common::ms_to_samples(end, sample_rate).min(impulse.len());
+        let start = common::ms_to_samples(start, sample_rate).min(end);
+        impulse.truncate(end);
+        impulse.drain(..start);
+        impulse
+    }
+}
+
+impl<G: ImpulseResponseGenerator + ?Sized> ImpulseResponseGenerator for Box<G> {
+    fn generate_with_rng(
+        &self,
+        sample_rate: u32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<f32> {
+        (**self).generate_with_rng(sample_rate, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Ones at every millisecond
+    struct Constant;
+
+    impl ImpulseResponseGenerator for Constant {
+        fn generate_with_rng(
+            &self,
+            sample_rate: u32,
+            _rng: &mut dyn RngCore,
+        ) -> Vec<f32> {
+            vec![1.0; sample_rate as usize / 1000]
+        }
+    }
+
+    #[test]
+    fn test_default_methods() {
+        assert_eq!(Constant.generate(16000), vec![1.0; 16]);
+
+        let rir = ImpulseResponseSimple::new(100.0, 10.0, 1.0, 10.0, -3.0);
+        // Samples before the direct sound are dropped
+        assert!((1500..=1600).contains(&rir.generate(16000).len()));
+    }
+
+    #[test]
+    fn test_boxed_generators() {
+        let generators: Vec<Box<dyn ImpulseResponseGenerator>> = vec![
+            Box::new(Constant),
+            Box::new(ImpulseResponseSimple::new(100.0, 10.0, 1.0, 10.0, -3.0)),
+            Box::new(ImpulseResponseImproved::new(100.0, 10.0, 1.0, 10.0, -3.0)),
+        ];
+        for generator in &generators {
+            let first =
+                generator.generate_with_rng(16000, &mut StdRng::seed_from_u64(1));
+            let second =
+                generator.generate_with_rng(16000, &mut StdRng::seed_from_u64(1));
+            assert!(!first.is_empty());
+            assert_eq!(first, second);
+        }
+    }
 }
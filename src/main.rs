@@ -1,121 +1,76 @@
-use std::fs;
-use std::path::{Path, PathBuf};
+use clap::{Parser, Subcommand};
 
-use clap::Parser;
-use ndarray_rand::rand::Rng;
-use storir::{
-    ImpulseResponseGenerator, ImpulseResponseImproved, ImpulseResponseSimple,
+mod commands;
+
+use commands::{
+    analyze::AnalyzeArgs, augment::AugmentArgs, compare::CompareArgs,
+    deconvolve::DeconvolveArgs, echo_density::EchoDensityArgs, extend::ExtendArgs,
+    generate::GenerateArgs, info::InfoArgs, inverse::InverseArgs,
+    preset::PresetsArgs, sweep::SweepArgs,
 };
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Algo
-    #[arg(short, long, default_value = "simple")]
-    algo: String,
-    /// Sample rate
-    #[arg(short, long, default_value = "44100")]
-    sample_rate: u32,
-    /// Folder to store wav files
-    #[arg(short, long, default_value = "impulses")]
-    folder: String,
-    /// Number of impulses to generate
-    #[arg(short, long, default_value = "5")]
-    num_impulses: u32,
-    /// Reverberation time in [ms]
-    #[arg(long, default_value = "500")]
-    rt60: u32,
-    /// Early decay time [ms]
-    #[arg(long, default_value = "50")]
-    edt: u32,
-    /// Initial time delay gap [ms]
-    #[arg(long, default_value = "4")]
-    itdg: u32,
-    /// Early reflections duration [ms]
-    #[arg(long, default_value = "100")]
-    er_duration: u32,
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    generate: GenerateArgs,
 }
 
-fn create_wav_file<P: AsRef<Path>>(
-    data: Vec<f32>,
-    sample_rate: u32,
-    file_name: P,
-) -> Result<(), hound::Error> {
-    let spec = hound::WavSpec {
-        channels: 1,
-        sample_rate,
-        bits_per_sample: 16,
-        sample_format: hound::SampleFormat::Int,
-    };
-
-    let max_amplitude = i16::MAX as f32;
-    let mut writer = hound::WavWriter::create(file_name, spec)?;
-    for sample in data {
-        let amplitude = (sample * max_amplitude).round() as i16;
-        writer.write_sample(amplitude)?;
-    }
-
-    writer.finalize()
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate impulse responses (default)
+    Generate(GenerateArgs),
+    /// Compare two impulse responses
+    Compare(CompareArgs),
+    /// Convolve dry recordings with random impulse responses
+    Augment(AugmentArgs),
+    /// Replace the noise floor of a measured impulse response with a
+    /// synthetic tail
+    Extend(ExtendArgs),
+    /// Measure every impulse response in a folder
+    Analyze(AnalyzeArgs),
+    /// Show embedded parameters and measured metrics of an impulse response
+    Info(InfoArgs),
+    /// Export regularized inverse filter of an impulse response
+    Inverse(InverseArgs),
+    /// Extract an impulse response from a sine sweep recording
+    Deconvolve(DeconvolveArgs),
+    /// Echo density profile of an impulse response
+    EchoDensity(EchoDensityArgs),
+    /// Export an exponential sine sweep for measurements
+    Sweep(SweepArgs),
+    /// List saved presets
+    Presets(PresetsArgs),
+    /// Serve impulse responses over HTTP
+    #[cfg(feature = "serve")]
+    Serve(commands::serve::ServeArgs),
 }
 
 fn main() {
-    let args = Args::parse();
-
-    // Save to folder
-    println!("Saving impulses to {}!", args.folder);
-    if !Path::new(&args.folder).exists() {
-        match fs::create_dir(&args.folder) {
-            Ok(_) => println!(
-                "No such folder found, crate new one '{}' ...",
-                args.folder
-            ),
-            Err(err) => eprint!("Error creating folder {} : {}", args.folder, err),
-        }
-    } else {
-        println!("'{}' folder already exists...", args.folder)
-    };
-
-    let mut rng = ndarray_rand::rand::thread_rng();
-    let drr = (args.rt60 as f32 * (-1.0 / 100.0))
-        + rng.gen_range(0.0..args.rt60 as f32 * (1.0 / 100.0));
-
-    let rir: Box<dyn ImpulseResponseGenerator> = match args.algo.as_str() {
-        "simple" => Box::new(ImpulseResponseSimple::new(
-            args.rt60 as f32,
-            args.edt as f32,
-            args.itdg as f32,
-            args.er_duration as f32,
-            drr,
-        )),
-        "improved" => Box::new(ImpulseResponseImproved::new(
-            args.rt60 as f32,
-            args.edt as f32,
-            args.itdg as f32,
-            args.er_duration as f32,
-            drr,
-        )),
-        _ => panic!("Wrong algo! should be in [simple, improved]"),
-    };
-
-    for index in 1..=args.num_impulses {
-        // Platform independent filepath
-        let mut path_buf = PathBuf::new();
-        let file_name = format!(
-            "{}_rt60_{}_edt_{}_itdg_{}_erd_{}_i{}.wav",
-            args.algo, args.rt60, args.edt, args.itdg, args.er_duration, index
-        );
-        path_buf.push(args.folder.clone());
-        path_buf.push(file_name);
+    let cli = Cli::parse();
 
-        let impulse = rir.generate(args.sample_rate);
-        match create_wav_file(impulse, args.sample_rate, &path_buf) {
-            Ok(()) => {
-                println!(
-                    "WAV file '{}' created successfully.",
-                    path_buf.as_path().to_str().unwrap()
-                )
-            }
-            Err(e) => eprintln!("Error: {}", e),
-        };
+    match cli.command {
+        Some(Command::Generate(args)) => commands::generate::run(args),
+        Some(Command::Compare(args)) => commands::compare::run(args),
+        Some(Command::Augment(args)) => commands::augment::run(args),
+        Some(Command::Extend(args)) => commands::extend::run(args),
+        Some(Command::Analyze(args)) => commands::analyze::run(args),
+        Some(Command::Info(args)) => commands::info::run(args),
+        Some(Command::Inverse(args)) => commands::inverse::run(args),
+        Some(Command::Deconvolve(args)) => commands::deconvolve::run(args),
+        Some(Command::EchoDensity(args)) => commands::echo_density::run(args),
+        Some(Command::Sweep(args)) => commands::sweep::run(args),
+        Some(Command::Presets(args)) => commands::preset::run(args),
+        #[cfg(feature = "serve")]
+        Some(Command::Serve(args)) => commands::serve::run(args),
+        None => commands::generate::run(cli.generate),
     }
 }
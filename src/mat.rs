@@ -0,0 +1,254 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::RirParams;
+
+/// Data types of MAT-file elements
+const MI_INT8: u32 = 1;
+const MI_UINT16: u32 = 4;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_SINGLE: u32 = 7;
+const MI_DOUBLE: u32 = 9;
+const MI_UINT64: u32 = 13;
+const MI_MATRIX: u32 = 14;
+
+/// MATLAB array classes
+const MX_STRUCT_CLASS: u32 = 2;
+const MX_CHAR_CLASS: u32 = 4;
+const MX_DOUBLE_CLASS: u32 = 6;
+const MX_SINGLE_CLASS: u32 = 7;
+const MX_UINT64_CLASS: u32 = 15;
+
+/// Fields of the `params` struct array, in file order
+const PARAM_FIELDS: [&str; 8] = [
+    "name",
+    "algo",
+    "rt60",
+    "edt",
+    "itdg",
+    "er_duration",
+    "drr",
+    "seed",
+];
+
+/// Impulse response with the parameters it was generated with
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatImpulse {
+    pub name: String,
+    pub algo: String,
+    pub params: RirParams,
+    pub seed: Option<u64>,
+    pub samples: Vec<f32>,
+}
+
+/// Write a batch of impulses as uncompressed MATLAB v5 MAT-file.
+///
+/// Variables: `irs` (single, one zero padded column per impulse), `fs`
+/// (sample rate [Hz]) and `params` (1xN struct array with name, algo, rt60,
+/// edt, itdg, er_duration [ms], drr [dB] and uint64 seed, empty when
+/// unknown).
+pub fn write_mat_to<W: Write>(
+    impulses: &[MatImpulse],
+    sample_rate: u32,
+    mut writer: W,
+) -> io::Result<()> {
+    let mut header = format!(
+        "MATLAB 5.0 MAT-file, Platform: {}, Created by: storir {}",
+        std::env::consts::OS,
+        env!("CARGO_PKG_VERSION")
+    )
+    .into_bytes();
+    header.resize(116, b' ');
+    // Subsystem data offset, version and endian indicator
+    header.extend_from_slice(&[0; 8]);
+    header.extend_from_slice(&0x0100u16.to_le_bytes());
+    header.extend_from_slice(b"IM");
+    writer.write_all(&header)?;
+
+    let rows = impulses
+        .iter()
+        .map(|ir| ir.samples.len())
+        .max()
+        .unwrap_or(0);
+    let mut irs = Vec::with_capacity(rows * impulses.len() * 4);
+    for impulse in impulses {
+        let samples = impulse.samples.iter().chain(std::iter::repeat(&0.0));
+        for sample in samples.take(rows) {
+            irs.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+    writer.write_all(&matrix(
+        "irs",
+        MX_SINGLE_CLASS,
+        [rows, impulses.len()],
+        &element(MI_SINGLE, &irs),
+    ))?;
+    writer.write_all(&double("fs", sample_rate as f64))?;
+
+    let mut fields = Vec::new();
+    for impulse in impulses {
+        let params = &impulse.params;
+        fields.extend(text("", &impulse.name));
+        fields.extend(text("", &impulse.algo));
+        for value in [
+            params.rt60,
+            params.edt,
+            params.itdg,
+            params.er_duration,
+            params.drr,
+        ] {
+            fields.extend(double("", value as f64));
+        }
+        fields.extend(match impulse.seed {
+            Some(seed) => matrix(
+                "",
+                MX_UINT64_CLASS,
+                [1, 1],
+                &element(MI_UINT64, &seed.to_le_bytes()),
+            ),
+            None => matrix("", MX_UINT64_CLASS, [0, 0], &element(MI_UINT64, &[])),
+        });
+    }
+    // Field names are NUL padded to the longest name
+    let name_length = PARAM_FIELDS.iter().map(|f| f.len()).max().unwrap() + 1;
+    let mut names = Vec::with_capacity(name_length * PARAM_FIELDS.len());
+    for field in PARAM_FIELDS {
+        names.extend_from_slice(field.as_bytes());
+        names.resize(names.len() + name_length - field.len(), 0);
+    }
+    let mut body = element(MI_INT32, &(name_length as i32).to_le_bytes());
+    body.extend(element(MI_INT8, &names));
+    body.extend(fields);
+    writer.write_all(&matrix(
+        "params",
+        MX_STRUCT_CLASS,
+        [1, impulses.len()],
+        &body,
+    ))?;
+    writer.flush()
+}
+
+/// Store a batch of impulses as MAT-file, see `write_mat_to`
+pub fn save_mat<P: AsRef<Path>>(
+    impulses: &[MatImpulse],
+    sample_rate: u32,
+    file_name: P,
+) -> io::Result<()> {
+    write_mat_to(
+        impulses,
+        sample_rate,
+        BufWriter::new(File::create(file_name)?),
+    )
+}
+
+/// Data element: type, size and data padded to 8 bytes
+fn element(data_type: u32, data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len() + 16);
+    bytes.extend_from_slice(&data_type.to_le_bytes());
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
+    bytes.resize(bytes.len().next_multiple_of(8), 0);
+    bytes
+}
+
+/// Array element with flags, dimensions, name and already encoded `data`
+fn matrix(name: &str, class: u32, dims: [usize; 2], data: &[u8]) -> Vec<u8> {
+    let mut flags = class.to_le_bytes().to_vec();
+    flags.extend_from_slice(&[0; 4]);
+    let dims: Vec<u8> = dims
+        .iter()
+        .flat_map(|&d| (d as i32).to_le_bytes())
+        .collect();
+    let mut body = element(MI_UINT32, &flags);
+    body.extend(element(MI_INT32, &dims));
+    body.extend(element(MI_INT8, name.as_bytes()));
+    body.extend_from_slice(data);
+    element(MI_MATRIX, &body)
+}
+
+fn double(name: &str, value: f64) -> Vec<u8> {
+    matrix(
+        name,
+        MX_DOUBLE_CLASS,
+        [1, 1],
+        &element(MI_DOUBLE, &value.to_le_bytes()),
+    )
+}
+
+/// Char array, non-ASCII characters are stored as UTF-16 code units
+fn text(name: &str, value: &str) -> Vec<u8> {
+    let units: Vec<u8> = value.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    matrix(
+        name,
+        MX_CHAR_CLASS,
+        [1, units.len() / 2],
+        &element(MI_UINT16, &units),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_mat_layout() {
+        let params = RirParams::new(500.0, 50.0, 4.0, 100.0, -3.0);
+        let impulses = vec![
+            MatImpulse {
+                name: "a".to_string(),
+                algo: "simple".to_string(),
+                params,
+                seed: Some(42),
+                samples: vec![1.0, 0.5, 0.25],
+            },
+            MatImpulse {
+                name: "b".to_string(),
+                algo: "improved".to_string(),
+                params,
+                seed: None,
+                samples: vec![1.0, -0.5],
+            },
+        ];
+        let mut bytes = Vec::new();
+        write_mat_to(&impulses, 16000, &mut bytes).unwrap();
+
+        assert!(bytes.starts_with(b"MATLAB 5.0 MAT-file"));
+        assert_eq!(&bytes[124..128], &[0x00, 0x01, b'I', b'M']);
+        assert_eq!(bytes.len() % 8, 0);
+
+        // Top level elements are the three matrices, each padded to 8 bytes
+        let mut offset = 128;
+        let mut names = Vec::new();
+        while offset < bytes.len() {
+            assert_eq!(read_u32(&bytes, offset), MI_MATRIX);
+            let size = read_u32(&bytes, offset + 4) as usize;
+            // Flags (16 bytes) and dimensions (16 bytes) precede the name
+            let name = offset + 8 + 32;
+            let name_size = read_u32(&bytes, name + 4) as usize;
+            names.push(
+                String::from_utf8(bytes[name + 8..name + 8 + name_size].to_vec())
+                    .unwrap(),
+            );
+            offset += 8 + size;
+        }
+        assert_eq!(offset, bytes.len());
+        assert_eq!(names, ["irs", "fs", "params"]);
+
+        // irs is 3x2 with the second column zero padded
+        let irs = 128 + 8;
+        assert_eq!(read_u32(&bytes, irs + 16 + 8), 3);
+        assert_eq!(read_u32(&bytes, irs + 16 + 12), 2);
+        let data = irs + 32 + 16;
+        assert_eq!(read_u32(&bytes, data), MI_SINGLE);
+        let samples: Vec<f32> = (0..6)
+            .map(|i| f32::from_bits(read_u32(&bytes, data + 8 + 4 * i)))
+            .collect();
+        assert_eq!(samples, [1.0, 0.5, 0.25, 1.0, -0.5, 0.0]);
+    }
+}
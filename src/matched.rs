@@ -0,0 +1,110 @@
+use ndarray_rand::rand::Rng;
+
+use crate::common::ms_to_samples;
+use crate::ImpulseResponseGenerator;
+
+/// Position dependent part of one impulse in a matched set, e.g. a close and
+/// a far microphone in the same room.
+///
+/// drr: direct to reverberant ratio [dB]
+/// pre_delay: silence before the direct sound [ms], rounded to samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub drr: f32,
+    pub pre_delay: f32,
+}
+
+impl Placement {
+    pub fn new(drr: f32, pre_delay: f32) -> Self {
+        if pre_delay < 0.0 {
+            panic!("Pre-delay should not be negative")
+        };
+        Self { drr, pre_delay }
+    }
+}
+
+/// Generate one impulse per placement, all sharing a single realization.
+///
+/// Reflection timing and signs are identical across the set, only the level
+/// of the tail relative to the direct sound and the silence before it
+/// change, so paired data differs in distance cues alone. DRR is set the way
+/// the generators define it, direct sample against the summed magnitude of
+/// the rest. The reflection density stays the one thinned for the
+/// generator's own DRR. Every impulse peaks at full scale.
+pub fn generate_matched<G, R>(
+    generator: &G,
+    placements: &[Placement],
+    sample_rate: u32,
+    rng: &mut R,
+) -> Vec<Vec<f32>>
+where
+    G: ImpulseResponseGenerator + ?Sized,
+    R: Rng,
+{
+    let impulse = generator.generate_with_rng(sample_rate, rng);
+    let direct = impulse.first().map_or(0.0, |x| x.abs() as f64);
+    let tail: f64 = impulse.iter().skip(1).map(|x| x.abs() as f64).sum();
+
+    placements
+        .iter()
+        .map(|placement| {
+            // Tail gain that moves the DRR from its generated value to the
+            // placement one
+            let gain = if tail > 0.0 {
+                (direct / tail / 10.0_f64.powf(placement.drr as f64 / 10.0)) as f32
+            } else {
+                1.0
+            };
+            let offset = ms_to_samples(placement.pre_delay, sample_rate);
+            let mut matched = vec![0.0; offset + impulse.len()];
+            for (i, &x) in impulse.iter().enumerate() {
+                matched[offset + i] = if i == 0 { x } else { x * gain };
+            }
+            let peak = matched.iter().fold(0.0_f32, |m, x| m.max(x.abs()));
+            if peak > 0.0 {
+                matched.iter_mut().for_each(|x| *x /= peak);
+            }
+            matched
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ImpulseResponseImproved;
+    use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_matched_pair() {
+        let rir = ImpulseResponseImproved::new(500.0, 50.0, 4.0, 80.0, -3.0);
+        let placements = [Placement::new(3.0, 0.0), Placement::new(-9.0, 5.0)];
+        let pair = generate_matched(
+            &rir,
+            &placements,
+            16000,
+            &mut StdRng::seed_from_u64(8),
+        );
+        let (near, far) = (&pair[0], &pair[1][80..]);
+        assert_eq!(pair[1].len(), near.len() + 80);
+        assert!(pair[1][..80].iter().all(|&x| x == 0.0));
+
+        for (impulse, placement) in [near, far].iter().zip(&placements) {
+            let tail: f32 = impulse[1..].iter().map(|x| x.abs()).sum();
+            let drr = 10.0 * (impulse[0].abs() / tail).log10();
+            assert!((drr - placement.drr).abs() < 1e-3, "drr: {}", drr);
+        }
+
+        // Same reflections, only scaled
+        let ratio = far[1..]
+            .iter()
+            .zip(&near[1..])
+            .find(|(_, &n)| n != 0.0)
+            .map(|(f, n)| f / n)
+            .unwrap();
+        assert!(far[1..]
+            .iter()
+            .zip(&near[1..])
+            .all(|(f, n)| (f - n * ratio).abs() <= 1e-6 * n.abs().max(1.0)));
+    }
+}
@@ -0,0 +1,92 @@
+use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+use crate::{ImpulseResponseGenerator, RirParams};
+
+/// Parameters between two conditions, `t` from 0.0 (`a`) to 1.0 (`b`).
+///
+/// All parameters are interpolated linearly, so rt60 stays above edt when it
+/// does in both conditions.
+pub fn morph(a: &RirParams, b: &RirParams, t: f32) -> RirParams {
+    if !(0.0..=1.0).contains(&t) {
+        panic!("Morphing position should be in range [0, 1]")
+    };
+    let lerp = |x: f32, y: f32| x + (y - x) * t;
+    RirParams::new(
+        lerp(a.rt60, b.rt60),
+        lerp(a.edt, b.edt),
+        lerp(a.itdg, b.itdg),
+        lerp(a.er_duration, b.er_duration),
+        lerp(a.drr, b.drr),
+    )
+}
+
+/// Impulses for `steps` evenly spaced positions from `a` to `b` (both
+/// included).
+///
+/// Every step is generated from the same seed, neighbouring impulses share
+/// their random structure and change gradually, unlike crossfading two
+/// independent responses. `build` makes the generator for given parameters.
+pub fn morph_impulses<G, F>(
+    a: &RirParams,
+    b: &RirParams,
+    steps: usize,
+    sample_rate: u32,
+    seed: u64,
+    build: F,
+) -> Vec<Vec<f32>>
+where
+    G: ImpulseResponseGenerator,
+    F: Fn(&RirParams) -> G,
+{
+    (0..steps)
+        .map(|step| {
+            let t = if steps > 1 {
+                step as f32 / (steps - 1) as f32
+            } else {
+                0.0
+            };
+            build(&morph(a, b, t))
+                .generate_with_rng(sample_rate, &mut StdRng::seed_from_u64(seed))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::correlation;
+    use crate::ImpulseResponseImproved;
+
+    #[test]
+    fn test_morph() {
+        let a = RirParams::new(400.0, 40.0, 2.0, 50.0, -2.0);
+        let b = RirParams::new(1200.0, 80.0, 10.0, 150.0, -10.0);
+        assert_eq!(morph(&a, &b, 0.0), a);
+        assert_eq!(morph(&a, &b, 1.0), b);
+        let middle = morph(&a, &b, 0.5);
+        assert_eq!(middle, RirParams::new(800.0, 60.0, 6.0, 100.0, -6.0));
+    }
+
+    #[test]
+    fn test_morph_impulses_are_correlated() {
+        let a = RirParams::new(500.0, 50.0, 4.0, 100.0, -3.0);
+        let b = RirParams::new(520.0, 52.0, 4.0, 100.0, -3.0);
+        let build = |p: &RirParams| {
+            ImpulseResponseImproved::new(
+                p.rt60,
+                p.edt,
+                p.itdg,
+                p.er_duration,
+                p.drr,
+            )
+        };
+        let impulses = morph_impulses(&a, &b, 3, 16000, 9, build);
+        assert_eq!(impulses.len(), 3);
+
+        let independent =
+            build(&b).generate_with_rng(16000, &mut StdRng::seed_from_u64(10));
+        let neighbours = correlation(&impulses[0], &impulses[1]);
+        assert!(neighbours > 0.9, "{}", neighbours);
+        assert!(neighbours > correlation(&impulses[0], &independent));
+    }
+}
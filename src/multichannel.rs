@@ -0,0 +1,206 @@
+use ndarray_rand::rand::Rng;
+
+use crate::analysis::linear_fit;
+use crate::common::ms_to_samples;
+use crate::ImpulseResponseGenerator;
+
+/// Generate impulse responses for several channels with controlled
+/// inter-channel cross-correlation (IACC for two ears).
+///
+/// Every channel keeps the direct sound and the first `early` ms after it
+/// from its own realization. Later samples are taken from one common
+/// realization, moved onto the decay of the channel's own tail, with a
+/// probability (the weight) or else from the channel's own one, so tails keep
+/// the density and levels of a single realization and DRR and EDT of every
+/// channel stay close to those of its realization. The weight is searched
+/// so that the average normalized cross-correlation of these late tails
+/// between channel pairs hits `iacc`. Targets below the correlation of fully
+/// independent tails (or above 1.0) end up at the closest reachable value.
+///
+/// Tails are matched on lines fitted to their levels instead of their sums,
+/// the sum of a sparse tail hangs on its few strongest samples and would
+/// shift the whole tail by decibels.
+pub fn generate_multichannel<G, R>(
+    generator: &G,
+    channels: usize,
+    iacc: f32,
+    early: f32,
+    sample_rate: u32,
+    rng: &mut R,
+) -> Vec<Vec<f32>>
+where
+    G: ImpulseResponseGenerator + ?Sized,
+    R: Rng,
+{
+    let common = generator.generate_with_rng(sample_rate, rng);
+    if channels < 2 {
+        return vec![common; channels];
+    }
+    let independent: Vec<Vec<f32>> = (0..channels)
+        .map(|_| generator.generate_with_rng(sample_rate, rng))
+        .collect();
+    let len = independent
+        .iter()
+        .map(Vec::len)
+        .fold(common.len(), usize::min);
+    let late = (1 + ms_to_samples(early, sample_rate)).min(len);
+    let draws: Vec<Vec<f32>> = (0..channels)
+        .map(|_| (late..len).map(|_| rng.gen()).collect())
+        .collect();
+
+    // Bisection over the weight of the common tail, correlation grows with it
+    let (mut low, mut high) = (0.0_f32, 1.0_f32);
+    for _ in 0..30 {
+        let weight = (low + high) / 2.0;
+        let mixed = mix_tails(&common, &independent, &draws, weight, late);
+        if mean_correlation(&mixed, late) < iacc as f64 {
+            low = weight;
+        } else {
+            high = weight;
+        }
+    }
+
+    mix_tails(&common, &independent, &draws, (low + high) / 2.0, late)
+}
+
+/// Channels made of their own direct sound and early part followed by tails
+/// from `late` on. Every tail sample turns from the channel's own to the
+/// common one as `weight` passes its draw, blended over a step of one sample
+/// count so that the correlation changes continuously with the weight.
+fn mix_tails(
+    common: &[f32],
+    independent: &[Vec<f32>],
+    draws: &[Vec<f32>],
+    weight: f32,
+    late: usize,
+) -> Vec<Vec<f32>> {
+    independent
+        .iter()
+        .zip(draws)
+        .map(|(own, draws)| {
+            let len = late + draws.len();
+            let step = draws.len().max(1) as f32;
+            let mut channel = own[..len].to_vec();
+            // Common samples are moved onto the decay of the channel's own tail
+            let (slope, start) =
+                match (log_decay(&own[late..len]), log_decay(&common[late..len])) {
+                    (Some(own), Some(common)) => {
+                        (own.0 - common.0, own.1 - common.1)
+                    }
+                    _ => (0.0, 0.0),
+                };
+            for ((j, i), &draw) in (late..len).enumerate().zip(draws) {
+                let share = ((weight - draw) * step).clamp(0.0, 1.0);
+                let scale = (start + slope * j as f64).exp() as f32;
+                channel[i] = share * scale * common[i] + (1.0 - share) * own[i];
+            }
+            channel
+        })
+        .collect()
+}
+
+/// Line (slope, start) fitted to the natural logarithm of the non-zero
+/// samples over their index, unlike the energies it is not dominated by the
+/// few strongest ones
+fn log_decay(samples: &[f32]) -> Option<(f64, f64)> {
+    let points: Vec<(f64, f64)> = samples
+        .iter()
+        .enumerate()
+        .filter(|(_, &x)| x > 0.0)
+        .map(|(i, &x)| (i as f64, (x as f64).ln()))
+        .collect();
+    linear_fit(&points)
+}
+
+/// Average normalized cross-correlation of tails from `late` on over all
+/// channel pairs
+fn mean_correlation(channels: &[Vec<f32>], late: usize) -> f64 {
+    let mut sum = 0.0;
+    let mut pairs = 0;
+    for (i, a) in channels.iter().enumerate() {
+        for b in &channels[i + 1..] {
+            let (a, b) = (&a[late..], &b[late..]);
+            let cross: f64 = a.iter().zip(b).map(|(&x, &y)| (x * y) as f64).sum();
+            sum += cross / (energy(a) * energy(b)).sqrt();
+            pairs += 1;
+        }
+    }
+    sum / pairs as f64
+}
+
+fn energy(samples: &[f32]) -> f64 {
+    samples.iter().map(|&x| (x as f64).powi(2)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::iacc;
+    use crate::verification::{Measurement, Tolerances};
+    use crate::{ImpulseResponseImproved, ImpulseResponseSimple, RirParams};
+    use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_stereo_iacc() {
+        let rir = ImpulseResponseImproved::new(500.0, 50.0, 5.0, 50.0, -1.0);
+        let mut rng = StdRng::seed_from_u64(3);
+        let channels = generate_multichannel(&rir, 2, 0.95, 55.0, 16000, &mut rng);
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].len(), channels[1].len());
+
+        let late = 1 + ms_to_samples(55.0, 16000);
+        let result = iacc(&channels[0][late..], &channels[1][late..], 16000);
+        assert!((result - 0.95).abs() < 0.01, "iacc: {}", result);
+    }
+
+    #[test]
+    fn test_channels_meet_targets() {
+        let params = RirParams {
+            rt60: 500.0,
+            edt: 50.0,
+            itdg: 4.0,
+            er_duration: 100.0,
+            drr: -3.0,
+        };
+        let rir = ImpulseResponseImproved::new(500.0, 50.0, 4.0, 100.0, -3.0);
+        let tolerances = Tolerances {
+            rt60: 0.1,
+            edt: 0.1,
+            drr: 1.0,
+        };
+        for seed in 0..3 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            for channel in
+                generate_multichannel(&rir, 2, 0.3, 104.0, 16000, &mut rng)
+            {
+                let measured = Measurement::measure(&channel, 16000);
+                assert!(measured.within(&params, &tolerances), "{measured:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_channels_meet_targets() {
+        // Near 0 dB DRR tails are sparse, their sums hang on a few samples
+        let params = RirParams {
+            rt60: 500.0,
+            edt: 50.0,
+            itdg: 4.0,
+            er_duration: 100.0,
+            drr: -0.9,
+        };
+        let rir = ImpulseResponseSimple::new(500.0, 50.0, 4.0, 100.0, -0.9);
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            for channel in
+                generate_multichannel(&rir, 2, 0.3, 104.0, 44100, &mut rng)
+            {
+                let measured = Measurement::measure(&channel, 44100);
+                assert!(
+                    measured.within(&params, &Tolerances::default()),
+                    "{measured:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::ops::RangeInclusive;
+
+use ndarray_rand::rand::Rng;
+
+/// Parameters of stochastic impulse response.
+///
+/// rt60: reverberation time [ms]
+/// edt: early decay time [ms]
+/// itdg: initial time delay gap [ms]
+/// er_duration: early reflections duration [ms]
+/// drr: direct to reverberant energy ratio [dB]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RirParams {
+    pub rt60: f32,
+    pub edt: f32,
+    pub itdg: f32,
+    pub er_duration: f32,
+    pub drr: f32,
+}
+
+impl RirParams {
+    pub fn new(rt60: f32, edt: f32, itdg: f32, er_duration: f32, drr: f32) -> Self {
+        Self {
+            rt60,
+            edt,
+            itdg,
+            er_duration,
+            drr,
+        }
+    }
+
+    /// Random DRR in range [-rt60 / 100, 0) dB, longer rooms get lower DRR
+    pub fn random_drr<R: Rng + ?Sized>(rt60: f32, rng: &mut R) -> f32 {
+        (rt60 * (-1.0 / 100.0)) + rng.gen_range(0.0..rt60 * (1.0 / 100.0))
+    }
+}
+
+/// Ranges to draw random impulse response parameters from [ms].
+///
+/// DRR is not sampled directly but follows rt60, see `RirParams::random_drr`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamRanges {
+    pub rt60: RangeInclusive<f32>,
+    pub edt: RangeInclusive<f32>,
+    pub itdg: RangeInclusive<f32>,
+    pub er_duration: RangeInclusive<f32>,
+}
+
+impl ParamRanges {
+    /// Draw uniformly distributed parameters, edt is capped at half of rt60
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> RirParams {
+        let rt60 = rng.gen_range(self.rt60.clone());
+        let edt = rng.gen_range(self.edt.clone()).min(rt60 / 2.0);
+        RirParams {
+            rt60,
+            edt,
+            itdg: rng.gen_range(self.itdg.clone()),
+            er_duration: rng.gen_range(self.er_duration.clone()),
+            drr: RirParams::random_drr(rt60, rng),
+        }
+    }
+}
+
+impl Default for ParamRanges {
+    fn default() -> Self {
+        Self {
+            rt60: 200.0..=1000.0,
+            edt: 20.0..=100.0,
+            itdg: 1.0..=10.0,
+            er_duration: 50.0..=150.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_within_ranges() {
+        let ranges = ParamRanges {
+            edt: 100.0..=400.0,
+            ..ParamRanges::default()
+        };
+        let mut rng = ndarray_rand::rand::thread_rng();
+        for _ in 0..100 {
+            let params = ranges.sample(&mut rng);
+            assert!(ranges.rt60.contains(&params.rt60));
+            assert!(params.edt < params.rt60);
+            assert!(ranges.itdg.contains(&params.itdg));
+            assert!(params.drr <= 0.0 && params.drr >= -params.rt60 / 100.0);
+        }
+    }
+}
@@ -1,13 +1,23 @@
+use crate::common::{
+    is_supported_sample_rate, is_valid_duration, sum_f64, MAX_SAMPLE_RATE,
+    MIN_SAMPLE_RATE,
+};
 use crate::{decibels_to_gain, ImpulseResponseGenerator};
 use ndarray::prelude::*;
 use ndarray_rand::{
-    rand::seq::SliceRandom, rand::thread_rng, rand_distr::Uniform, RandomExt,
+    rand::seq::SliceRandom, rand::Rng, rand::RngCore, rand_distr::Uniform,
+    RandomExt,
 };
 use ndarray_stats::QuantileExt;
 use std::cmp::Ordering;
 use std::time::Duration;
 
-/// Stochastic impulse response.
+/// Stochastic impulse response, the reference stoRIR algorithm.
+///
+/// Defines DRR and ITDG the same way as `ImpulseResponseImproved` and has the
+/// same EDT/RT60 ramp, but has no options on top (clarity, crest factor,
+/// custom decay) and thins reflections in an order which changes with the
+/// impulse length, so same seed impulses of different lengths don't match.
 ///
 /// rt60: reverberation time [ms]
 /// edt: early decay time [ms]
@@ -24,24 +34,45 @@ pub struct ImpulseResponseSimple {
 }
 
 impl ImpulseResponseGenerator for ImpulseResponseSimple {
-    /// Generate impulse response
-    fn generate(&self, sample_rate: u32) -> Vec<f32> {
-        let mut noise = self.get_noise(sample_rate);
+    /// Generate impulse response using given random number generator
+    fn generate_with_rng(
+        &self,
+        sample_rate: u32,
+        rng: &mut dyn RngCore,
+    ) -> Vec<f32> {
+        if !is_supported_sample_rate(sample_rate) {
+            panic!(
+                "Sample rate {} is out of supported range [{}, {}]",
+                sample_rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+            )
+        };
+        let mut noise = self.get_noise(sample_rate, rng);
         let (dsi, ersi, erei) =
             self.get_edt_and_rt60_slope(&mut noise, sample_rate);
-        self.randomize_reflections(&mut noise, dsi, ersi, erei, sample_rate);
-        noise.into_raw_vec()[dsi..].to_vec()
+        self.randomize_reflections(&mut noise, dsi, ersi, erei, sample_rate, rng);
+        // Drop samples before the direct sound in place, the noise buffer
+        // becomes the output without another allocation
+        let mut impulse = noise.into_raw_vec();
+        impulse.drain(..dsi);
+        impulse
     }
 }
 
 impl ImpulseResponseSimple {
     /// Random noize (white)
-    fn get_noise(&self, sample_rate: u32) -> Array1<f32> {
-        let num_samples = Self::get_num_samples(
-            Duration::from_millis(self.rt60.round() as u64),
-            sample_rate,
-        );
-        Array1::random(num_samples as usize, Uniform::new(-5.0, 5.0))
+    fn get_noise<R: Rng + ?Sized>(
+        &self,
+        sample_rate: u32,
+        rng: &mut R,
+    ) -> Array1<f32> {
+        let num_samples =
+            Self::get_num_samples(Self::ms_to_duration(self.rt60), sample_rate);
+        // At least the direct sound and a single reflection
+        Array1::random_using(
+            num_samples.max(2) as usize,
+            Uniform::new(-5.0, 5.0),
+            rng,
+        )
     }
 
     fn get_edt_and_rt60_slope(
@@ -49,16 +80,14 @@ impl ImpulseResponseSimple {
         data: &mut Array1<f32>,
         sample_rate: u32,
     ) -> (usize, usize, usize) {
-        let edt_num_samples = Self::get_num_samples(
-            Duration::from_millis(self.edt.round() as u64),
-            sample_rate,
-        );
-        let rt60_num_samples = Self::get_num_samples(
-            Duration::from_millis(self.rt60.round() as u64),
-            sample_rate,
-        );
+        // Short EDT may map to zero samples at low sample rates
+        let edt_num_samples =
+            Self::get_num_samples(Self::ms_to_duration(self.edt), sample_rate)
+                .max(1);
+        let rt60_num_samples =
+            Self::get_num_samples(Self::ms_to_duration(self.rt60), sample_rate);
         let er_duration_num_samples = Self::get_num_samples(
-            Duration::from_millis(self.er_duration.round() as u64),
+            Self::ms_to_duration(self.er_duration),
             sample_rate,
         );
 
@@ -71,10 +100,11 @@ impl ImpulseResponseSimple {
         }
         *data *= 10.0 / edt_num_samples as f32;
 
-        // Shape the RT60 slope of the IR (after EDT)
+        // Shape the RT60 slope of the IR (after EDT), computed in f64 since
+        // sample indices of long tails are not exactly representable in f32
         for i in edt_num_samples..rt60_num_samples {
-            data[i as usize] -= (i as f32 - (edt_num_samples + 1) as f32) * 50.0
-                / rt60_num_samples as f32;
+            data[i as usize] -= ((i as f64 - (edt_num_samples + 1) as f64) * 50.0
+                / rt60_num_samples as f64) as f32;
         }
 
         // Change scale to dBFS (0 dB becomes the maximal level)
@@ -101,18 +131,19 @@ impl ImpulseResponseSimple {
         (direct_sound_idx, er_start_idx, er_end_idx)
     }
 
-    fn randomize_reflections(
+    fn randomize_reflections<R: Rng + ?Sized>(
         &self,
         data: &mut Array1<f32>,
         direct_sound_idx: usize,
         early_ref_start: usize,
         early_ref_end: usize,
         sample_rate: u32,
+        rng: &mut R,
     ) {
         self.create_initial_time_delay_gap(data, direct_sound_idx, sample_rate);
 
-        let drr_low = self.drr - 0.5;
-        let drr_high = self.drr + 0.5;
+        let drr_low = self.drr as f64 - 0.5;
+        let drr_high = self.drr as f64 + 0.5;
 
         let mut current_drr =
             Self::calculate_drr_energy_ratio(data, direct_sound_idx);
@@ -128,6 +159,7 @@ impl ImpulseResponseSimple {
                 early_ref_start,
                 early_ref_end,
                 1.0 / 8.0,
+                rng,
             );
 
             // Thin out reverberation tail
@@ -136,6 +168,7 @@ impl ImpulseResponseSimple {
                 early_ref_end,
                 data.len() - 1,
                 1.0 / 10.0,
+                rng,
             );
 
             let previous_drr = current_drr;
@@ -143,7 +176,7 @@ impl ImpulseResponseSimple {
 
             // If thinning out reflections did not decrease the DRR, it means
             // that the maximal DRR possible has been reached
-            if (previous_drr - current_drr).abs() < std::f32::EPSILON {
+            if (previous_drr - current_drr).abs() < f64::EPSILON {
                 break;
             }
         }
@@ -155,18 +188,14 @@ impl ImpulseResponseSimple {
         direct_sound_idx: usize,
         sample_rate: u32,
     ) {
-        let itdg_num_samples = Self::get_num_samples(
-            Duration::from_millis(self.itdg.round() as u64),
-            sample_rate,
-        );
+        let itdg_num_samples =
+            Self::get_num_samples(Self::ms_to_duration(self.itdg), sample_rate);
         let itdg_end_idx = usize::min(
             direct_sound_idx + 1 + itdg_num_samples as usize,
             data.len() - 1,
         );
-        for elem in data
-            .slice_mut(s![direct_sound_idx + 1..itdg_end_idx])
-            .iter_mut()
-        {
+        let itdg_start_idx = (direct_sound_idx + 1).min(itdg_end_idx);
+        for elem in data.slice_mut(s![itdg_start_idx..itdg_end_idx]).iter_mut() {
             *elem = 0.0;
         }
     }
@@ -174,17 +203,19 @@ impl ImpulseResponseSimple {
     fn calculate_drr_energy_ratio(
         data: &Array1<f32>,
         direct_sound_idx: usize,
-    ) -> f32 {
-        let direct = data.slice(s![..=direct_sound_idx]).sum();
-        let reverberant = data.slice(s![direct_sound_idx + 1..]).sum();
+    ) -> f64 {
+        // Peak only, what precedes it is not part of the returned impulse
+        let direct = data[direct_sound_idx] as f64;
+        let reverberant = sum_f64(data.slice(s![direct_sound_idx + 1..]));
         10.0 * ((direct / reverberant).log10())
     }
 
-    fn thin_out_reflections(
+    fn thin_out_reflections<R: Rng + ?Sized>(
         data: &mut Array1<f32>,
         start_idx: usize,
         end_idx: usize,
         rate: f32,
+        rng: &mut R,
     ) {
         let ray_indices: Vec<usize> = (start_idx..=end_idx)
             .filter(|&idx| data[idx] != 0.0)
@@ -193,9 +224,8 @@ impl ImpulseResponseSimple {
 
         // assert!(num_rays >= 1);
         if num_rays >= 1 {
-            let mut rng = thread_rng();
             let random_subset: Vec<usize> = ray_indices
-                .choose_multiple(&mut rng, num_rays)
+                .choose_multiple(rng, num_rays)
                 .cloned()
                 .collect();
 
@@ -206,10 +236,23 @@ impl ImpulseResponseSimple {
     }
 
     fn get_num_samples(t: Duration, sample_rate: u32) -> u32 {
-        (t.as_secs_f32() * sample_rate as f32).round() as u32
+        (t.as_secs_f64() * sample_rate as f64).round() as u32
+    }
+
+    /// Fractional milliseconds to duration, keeps sub-millisecond precision,
+    /// negative and NaN times become zero
+    fn ms_to_duration(ms: f32) -> Duration {
+        Duration::try_from_secs_f64(ms.max(0.0) as f64 / 1000.0)
+            .unwrap_or(Duration::ZERO)
     }
 
     pub fn new(rt60: f32, edt: f32, itdg: f32, er_duration: f32, drr: f32) -> Self {
+        if ![rt60, edt, itdg, er_duration]
+            .into_iter()
+            .all(is_valid_duration)
+        {
+            panic!("Times (rt60, edt, itdg, er_duration) should be finite and non-negative")
+        };
         if rt60 <= edt {
             panic!("Reverb time (rt60) can't be lower than Early decay time (edt)")
         };
@@ -240,4 +283,26 @@ mod tests {
         }
         assert!(non_zero_elements > 0);
     }
+
+    #[test]
+    #[should_panic]
+    fn test_negative_itdg() {
+        ImpulseResponseSimple::new(500.0, 50.0, -1.0, 50.0, -1.0);
+    }
+
+    #[test]
+    fn test_drr_and_itdg_targets() {
+        use ndarray_rand::rand::{rngs::StdRng, SeedableRng};
+
+        // Direct sound is the first sample, 5 ms gap at 16 kHz
+        let gap = 80;
+        for target in [-6.0, 0.0] {
+            let rir = ImpulseResponseSimple::new(500.0, 50.0, 5.0, 50.0, target);
+            let impulse =
+                rir.generate_with_rng(16000, &mut StdRng::seed_from_u64(3));
+            assert!(impulse[1..=gap].iter().all(|&x| x == 0.0));
+            let drr = 10.0 * (impulse[0] as f64 / sum_f64(&impulse[1..])).log10();
+            assert!((drr - target as f64).abs() <= 0.5, "drr: {}", drr);
+        }
+    }
 }
@@ -0,0 +1,248 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::common::MAX_SAMPLE_RATE;
+
+/// Magic bytes of the binary sparse format
+const MAGIC: &[u8; 4] = b"SRIR";
+/// Version of the binary sparse format
+const VERSION: u8 = 1;
+/// Longest dense length accepted, ten minutes at the highest sample rate,
+/// bounds the memory a corrupt file can allocate
+pub const MAX_LEN: usize = 600 * MAX_SAMPLE_RATE as usize;
+
+/// Impulse response stored as (index, value) pairs of non-zero samples.
+///
+/// Thinning leaves most of the tail at exactly 0.0, so this is a lot smaller
+/// than dense storage and suits sparse convolution.
+///
+/// Binary layout (little endian): `SRIR`, version (u8), sample rate (u32),
+/// dense length (u32), pair count (u32), then pairs of index (u32) and
+/// value (f32). Dense length is at most `MAX_LEN` and above every index,
+/// reading, writing and `to_dense` fail with `InvalidData` otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseIr {
+    pub sample_rate: u32,
+    pub len: usize,
+    pub samples: Vec<(u32, f32)>,
+}
+
+impl SparseIr {
+    pub fn from_dense(ir: &[f32], sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            len: ir.len(),
+            samples: ir
+                .iter()
+                .enumerate()
+                .filter(|(_, &x)| x != 0.0)
+                .map(|(i, &x)| (i as u32, x))
+                .collect(),
+        }
+    }
+
+    /// Reconstruct dense impulse response
+    pub fn to_dense(&self) -> io::Result<Vec<f32>> {
+        self.validate()?;
+        let mut ir = vec![0.0; self.len];
+        for &(index, value) in &self.samples {
+            ir[index as usize] = value;
+        }
+        Ok(ir)
+    }
+
+    /// Errors unless the length is within `MAX_LEN` and above every index
+    fn validate(&self) -> io::Result<()> {
+        check_len(self.len)?;
+        match self
+            .samples
+            .iter()
+            .all(|&(index, _)| (index as usize) < self.len)
+        {
+            true => Ok(()),
+            false => Err(invalid_data("sample index out of range")),
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.validate()?;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&self.sample_rate.to_le_bytes())?;
+        writer.write_all(&(self.len as u32).to_le_bytes())?;
+        writer.write_all(&(self.samples.len() as u32).to_le_bytes())?;
+        for &(index, value) in &self.samples {
+            writer.write_all(&index.to_le_bytes())?;
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(invalid_data("not a sparse impulse response"));
+        }
+        let sample_rate = read_u32(&mut reader)?;
+        let len = check_len(read_u32(&mut reader)? as usize)?;
+        let count = read_u32(&mut reader)? as usize;
+        if count > len {
+            return Err(invalid_data("more samples than the length"));
+        }
+        let mut samples = Vec::with_capacity(count.min(len));
+        for _ in 0..count {
+            let index = read_u32(&mut reader)?;
+            let value = f32::from_bits(read_u32(&mut reader)?);
+            if index as usize >= len {
+                return Err(invalid_data("sample index out of range"));
+            }
+            samples.push((index, value));
+        }
+        Ok(Self {
+            sample_rate,
+            len,
+            samples,
+        })
+    }
+
+    /// CSV with `# sample_rate=..,len=..` comment line and `index,value` rows
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.validate()?;
+        writeln!(
+            writer,
+            "# sample_rate={},len={}",
+            self.sample_rate, self.len
+        )?;
+        writeln!(writer, "index,value")?;
+        for &(index, value) in &self.samples {
+            writeln!(writer, "{},{}", index, value)?;
+        }
+        writer.flush()
+    }
+
+    pub fn read_csv<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut lines = reader.lines();
+        let meta = lines.next().ok_or_else(|| invalid_data("empty file"))??;
+        let mut sample_rate = None;
+        let mut len = None;
+        for field in meta.trim_start_matches('#').trim().split(',') {
+            match field.split_once('=') {
+                Some(("sample_rate", v)) => sample_rate = v.parse().ok(),
+                Some(("len", v)) => len = v.parse().ok(),
+                _ => {}
+            }
+        }
+        let (sample_rate, len) = sample_rate
+            .zip(len)
+            .ok_or_else(|| invalid_data("missing sample_rate or len"))?;
+        let len = check_len(len)?;
+
+        let mut samples = Vec::new();
+        for line in lines.skip(1) {
+            let line = line?;
+            let (index, value) = line
+                .split_once(',')
+                .ok_or_else(|| invalid_data("expected index,value"))?;
+            let index: u32 = index.trim().parse().map_err(invalid_data)?;
+            let value: f32 = value.trim().parse().map_err(invalid_data)?;
+            if index as usize >= len {
+                return Err(invalid_data("sample index out of range"));
+            }
+            samples.push((index, value));
+        }
+        Ok(Self {
+            sample_rate,
+            len,
+            samples,
+        })
+    }
+
+    /// Store binary sparse file
+    pub fn save<P: AsRef<Path>>(&self, file_name: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(file_name)?))
+    }
+
+    /// Load binary sparse file
+    pub fn load<P: AsRef<Path>>(file_name: P) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(file_name)?))
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn check_len(len: usize) -> io::Result<usize> {
+    match len <= MAX_LEN {
+        true => Ok(len),
+        false => Err(invalid_data(format!("length {} exceeds {}", len, MAX_LEN))),
+    }
+}
+
+fn invalid_data<E: ToString>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_round_trip() {
+        let ir = vec![1.0, 0.0, 0.0, 0.25, 0.0, -0.125, 0.0, 0.0];
+        let sparse = SparseIr::from_dense(&ir, 16000);
+        assert_eq!(sparse.samples.len(), 3);
+
+        let mut binary = Vec::new();
+        sparse.write_to(&mut binary).unwrap();
+        let loaded = SparseIr::read_from(binary.as_slice()).unwrap();
+        assert_eq!(loaded, sparse);
+
+        let mut csv = Vec::new();
+        sparse.write_csv(&mut csv).unwrap();
+        let loaded = SparseIr::read_csv(csv.as_slice()).unwrap();
+        assert_eq!(loaded.to_dense().unwrap(), ir);
+    }
+
+    #[test]
+    fn test_invalid_sparse_data() {
+        let invalid = |result: io::Result<SparseIr>| {
+            result.is_err_and(|e| e.kind() == io::ErrorKind::InvalidData)
+        };
+        let binary = |len: u32, count: u32, index: u32| {
+            let mut data = b"SRIR\x01".to_vec();
+            for value in [16000, len, count, index, 0] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data
+        };
+        assert!(SparseIr::read_from(binary(8, 1, 7).as_slice()).is_ok());
+        assert!(invalid(SparseIr::read_from(binary(8, 1, 8).as_slice())));
+        assert!(invalid(SparseIr::read_from(binary(8, 9, 0).as_slice())));
+        assert!(invalid(SparseIr::read_from(
+            binary(u32::MAX, 1, 0).as_slice()
+        )));
+
+        let csv = |len: usize, index: u32| {
+            format!(
+                "# sample_rate=16000,len={}\nindex,value\n{},1.0\n",
+                len, index
+            )
+        };
+        assert!(invalid(SparseIr::read_csv(csv(4, 4).as_bytes())));
+        assert!(invalid(SparseIr::read_csv(csv(MAX_LEN + 1, 0).as_bytes())));
+
+        let sparse = SparseIr {
+            sample_rate: 16000,
+            len: 2,
+            samples: vec![(2, 1.0)],
+        };
+        let error = sparse.to_dense().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(sparse.write_to(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use ndarray_rand::rand::Rng;
+
+use crate::analysis::{linear_fit, peak_index};
+use crate::{ImpulseResponseGenerator, RirParams};
+
+/// Drop of the early decay over edt [dB]
+const EARLY_DECAY_DROP: f64 = 10.0;
+
+/// Distance of the late decay line from 10 dB below the direct sound at the
+/// end of the early decay up to which the early decay starts at the direct
+/// sound [dB]
+const KNEE_MISMATCH: f64 = 0.4;
+
+/// Spread of the early levels around a line from the direct sound up to
+/// which the early decay starts there (RMS) [dB]
+const ANCHORED_RESIDUAL: f64 = 0.5;
+
+/// Part of edt the early levels must span for their line from the direct
+/// sound to be trusted without checking it against the late decay
+const EARLY_SPAN: f64 = 0.25;
+
+/// Drop of the late decay over rt60 [dB]
+const LATE_DECAY_DROP: f64 = 50.0;
+
+/// Allowed deviation of the mean measured value from the target.
+///
+/// rt60: relative to the target, 0.1 is 10 %
+/// edt: relative to the target
+/// drr: absolute [dB]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerances {
+    pub rt60: f32,
+    pub edt: f32,
+    pub drr: f32,
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Self {
+            rt60: 0.2,
+            edt: 0.2,
+            drr: 3.0,
+        }
+    }
+}
+
+/// RT60, EDT and DRR of a generated impulse in the terms the generators
+/// define them.
+///
+/// Generator samples are energies (squared gains, their square roots are the
+/// amplitudes) on a decay envelope falling 10 dB over edt and 50 dB per rt60
+/// after that, thinned out reflections are zeros. `analysis` squares the
+/// samples once more and integrates Schroeder curves, which reads differently
+/// on the same impulse. Levels here are those of the non-zero samples
+/// relative to the peak (direct sound). Lines fitted to the early and late
+/// levels are free to start below the direct sound, so dense tails averaged
+/// over several realizations (lower levels of non-zero samples) read the
+/// same as sparse ones:
+///
+/// rt60: 50 dB over the slope fitted to the late levels [ms]
+/// edt: 10 dB over the slope fitted to the early levels [ms]
+/// drr: peak sample to the sum of all later samples [dB]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub rt60: Option<f32>,
+    pub edt: Option<f32>,
+    pub drr: f32,
+}
+
+impl Measurement {
+    pub fn measure(ir: &[f32], sample_rate: u32) -> Self {
+        let peak = peak_index(ir);
+        let direct = ir.get(peak).map_or(0.0, |x| x.abs() as f64);
+        let ms = |samples: usize| samples as f64 * 1000.0 / sample_rate as f64;
+        let levels: Vec<(f64, f64)> = ir
+            .iter()
+            .enumerate()
+            .skip(peak + 1)
+            .filter(|(_, &x)| x != 0.0)
+            .map(|(i, &x)| (ms(i - peak), 10.0 * (x.abs() as f64 / direct).log10()))
+            .collect();
+        // Early decay of a single realization starts at the direct sound and
+        // ends 10 dB below it, averaged ones start lower on a line of their own
+        let (early, late) = levels.split_at(
+            levels
+                .iter()
+                .position(|&(_, level)| level <= -EARLY_DECAY_DROP)
+                .unwrap_or(levels.len()),
+        );
+        let (late, slope) = match anchored_slope(early, late) {
+            Some(slope) => (late, Some(slope)),
+            None => {
+                let (early, late) = levels.split_at(decay_knee(&levels));
+                let slope = linear_fit(early)
+                    .map(|(slope, _)| slope)
+                    .filter(|&slope| slope < 0.0);
+                (late, slope)
+            }
+        };
+        let edt = match slope {
+            Some(slope) => Some((-EARLY_DECAY_DROP / slope) as f32),
+            None => late.first().map(|&(time, _)| time as f32),
+        };
+        let rt60 = match linear_fit(late) {
+            Some((slope, _)) if slope < 0.0 => {
+                Some((-LATE_DECAY_DROP / slope) as f32)
+            }
+            _ => None,
+        };
+        let reverberant: f64 =
+            ir.iter().skip(peak + 1).map(|x| x.abs() as f64).sum();
+        Self {
+            rt60,
+            edt,
+            drr: (10.0 * (direct / reverberant).log10()) as f32,
+        }
+    }
+
+    /// Every parameter measured and within `tolerances` of the target
+    pub fn within(&self, target: &RirParams, tolerances: &Tolerances) -> bool {
+        let relative = |measured: Option<f32>, target: f32, tolerance: f32| {
+            measured.is_some_and(|m| ((m - target) / target).abs() <= tolerance)
+        };
+        relative(self.rt60, target.rt60, tolerances.rt60)
+            && relative(self.edt, target.edt, tolerances.edt)
+            && (self.drr - target.drr).abs() <= tolerances.drr
+    }
+}
+
+/// Distribution of a measured parameter over the generated impulses.
+///
+/// count: number of impulses the parameter could be measured on, the
+/// statistics are NaN when it is 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f32,
+    pub std: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Summary {
+    pub fn from_values(values: &[f32]) -> Self {
+        let n = values.len() as f64;
+        let mean = values.iter().map(|&x| x as f64).sum::<f64>() / n;
+        let variance = values
+            .iter()
+            .map(|&x| (x as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        Self {
+            count: values.len(),
+            mean: mean as f32,
+            std: variance.sqrt() as f32,
+            min: values.iter().copied().fold(f32::NAN, f32::min),
+            max: values.iter().copied().fold(f32::NAN, f32::max),
+        }
+    }
+}
+
+/// Measured distribution of one parameter against its target, passes when
+/// every impulse could be measured and the mean is within tolerance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Check {
+    pub target: f32,
+    pub summary: Summary,
+    pub passed: bool,
+}
+
+/// Outcome of a verification run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    pub num_impulses: usize,
+    pub rt60: Check,
+    pub edt: Check,
+    pub drr: Check,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.rt60.passed && self.edt.passed && self.drr.passed
+    }
+}
+
+/// Statistical check of a generator: generate `num_impulses` impulses,
+/// measure RT60, EDT and DRR of each (see `Measurement`) and compare the means
+/// with the target parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Verification {
+    target: RirParams,
+    num_impulses: usize,
+    tolerances: Tolerances,
+}
+
+impl Verification {
+    pub fn new(target: RirParams, num_impulses: usize) -> Self {
+        if num_impulses == 0 {
+            panic!("Number of impulses should be positive")
+        };
+        Self {
+            target,
+            num_impulses,
+            tolerances: Tolerances::default(),
+        }
+    }
+
+    /// Allowed deviations of the means, `Tolerances::default()` otherwise
+    pub fn with_tolerances(mut self, tolerances: Tolerances) -> Self {
+        self.tolerances = tolerances;
+        self
+    }
+
+    /// Generate and measure the impulses
+    pub fn run<G: ImpulseResponseGenerator + ?Sized, R: Rng>(
+        &self,
+        generator: &G,
+        sample_rate: u32,
+        rng: &mut R,
+    ) -> Report {
+        let mut rt60s = Vec::with_capacity(self.num_impulses);
+        let mut edts = Vec::with_capacity(self.num_impulses);
+        let mut drrs = Vec::with_capacity(self.num_impulses);
+        for _ in 0..self.num_impulses {
+            let ir = generator.generate_with_rng(sample_rate, rng);
+            let measurement = Measurement::measure(&ir, sample_rate);
+            rt60s.extend(measurement.rt60);
+            edts.extend(measurement.edt);
+            drrs.extend(Some(measurement.drr).filter(|v| v.is_finite()));
+        }
+
+        let check = |values: &[f32], target: f32, tolerance: f32| {
+            let summary = Summary::from_values(values);
+            Check {
+                target,
+                summary,
+                passed: summary.count == self.num_impulses
+                    && (summary.mean - target).abs() <= tolerance,
+            }
+        };
+        Report {
+            num_impulses: self.num_impulses,
+            rt60: check(
+                &rt60s,
+                self.target.rt60,
+                self.tolerances.rt60 * self.target.rt60,
+            ),
+            edt: check(
+                &edts,
+                self.target.edt,
+                self.tolerances.edt * self.target.edt,
+            ),
+            drr: check(&drrs, self.target.drr, self.tolerances.drr),
+        }
+    }
+}
+
+/// Index splitting `levels` into the early and late decay, where two lines
+/// fitted on either side leave the smallest squared error. Both sides keep at
+/// least two levels, shorter input is all late decay.
+fn decay_knee(levels: &[(f64, f64)]) -> usize {
+    if levels.len() < 4 {
+        return 0;
+    }
+    // Running sums of t, l, t², t·l and l² give the error of any segment
+    let mut sums = vec![[0.0f64; 5]; levels.len() + 1];
+    for (i, &(t, l)) in levels.iter().enumerate() {
+        let point = [t, l, t * t, t * l, l * l];
+        for k in 0..5 {
+            sums[i + 1][k] = sums[i][k] + point[k];
+        }
+    }
+    let error = |from: usize, to: usize| {
+        let n = (to - from) as f64;
+        let s: Vec<f64> = (0..5).map(|k| sums[to][k] - sums[from][k]).collect();
+        let stt = s[2] - s[0] * s[0] / n;
+        let stl = s[3] - s[0] * s[1] / n;
+        let sll = s[4] - s[1] * s[1] / n;
+        match stt > 0.0 {
+            true => sll - stl * stl / stt,
+            false => sll,
+        }
+    };
+    (2..=levels.len() - 2)
+        .min_by(|&a, &b| {
+            let total = |k| error(0, k) + error(k, levels.len());
+            total(a).total_cmp(&total(b))
+        })
+        .unwrap()
+}
+
+/// Falling slope of the line from the direct sound (0 dB at 0 ms) fitted to
+/// `early`, if the early decay of a single realization starts there. Averaged
+/// realizations lie lower: their early levels spread further than
+/// `ANCHORED_RESIDUAL` (RMS) around the line, or when they span less than
+/// `EARLY_SPAN` of edt the line fitted to `late` does not meet it within
+/// `KNEE_MISMATCH` of 10 dB below the direct sound.
+fn anchored_slope(early: &[(f64, f64)], late: &[(f64, f64)]) -> Option<f64> {
+    let slope = early.iter().map(|&(t, l)| t * l).sum::<f64>()
+        / early.iter().map(|&(t, _)| t * t).sum::<f64>();
+    if slope >= 0.0 || slope.is_nan() {
+        return None;
+    }
+    if early.len() >= 2 {
+        let residual = early
+            .iter()
+            .map(|&(t, l)| (l - slope * t).powi(2))
+            .sum::<f64>()
+            / early.len() as f64;
+        if residual.sqrt() > ANCHORED_RESIDUAL {
+            return None;
+        }
+    }
+    let span = early.last().map_or(0.0, |&(t, _)| t) - early[0].0;
+    if span >= EARLY_SPAN * -EARLY_DECAY_DROP / slope {
+        return Some(slope);
+    }
+    match linear_fit(late) {
+        Some((late_slope, start)) => {
+            let knee = start - late_slope * EARLY_DECAY_DROP / slope;
+            ((knee + EARLY_DECAY_DROP).abs() <= KNEE_MISMATCH).then_some(slope)
+        }
+        None => Some(slope),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DecayProfile, ImpulseResponseImproved, ImpulseResponseSimple};
+    use ndarray_rand::rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    /// Energies on the EDT/RT60 envelope with half the reflections missing
+    struct Envelope {
+        edt: f32,
+        rt60: f32,
+    }
+
+    impl ImpulseResponseGenerator for Envelope {
+        fn generate_with_rng(
+            &self,
+            sample_rate: u32,
+            rng: &mut dyn RngCore,
+        ) -> Vec<f32> {
+            let decay = DecayProfile::edt_rt60(self.edt, self.rt60);
+            (0..sample_rate)
+                .map(|i| {
+                    let time = i as f32 * 1000.0 / sample_rate as f32;
+                    let level = decay.level(time) + rng.gen_range(-1.0..1.0);
+                    match i == 0 || rng.gen_bool(0.5) {
+                        true => 10.0_f32.powf(level.min(0.0) / 10.0),
+                        false => 0.0,
+                    }
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_measurement() {
+        let sample_rate = 16000;
+        let mut ir: Vec<f32> = (0..sample_rate)
+            .map(|i| {
+                let time = i as f32 * 1000.0 / sample_rate as f32;
+                10.0_f32
+                    .powf(DecayProfile::edt_rt60(50.0, 500.0).level(time) / 10.0)
+            })
+            .collect();
+        ir[1..8].iter_mut().for_each(|x| *x = 0.0);
+        let measurement = Measurement::measure(&ir, sample_rate);
+        assert!((measurement.edt.unwrap() - 50.0).abs() < 0.1);
+        assert!((measurement.rt60.unwrap() - 500.0).abs() < 1.0);
+        let reverberant: f32 = ir[1..].iter().sum();
+        assert!(
+            (measurement.drr - 10.0 * (1.0 / reverberant).log10()).abs() < 1e-3
+        );
+
+        let target = RirParams::new(500.0, 50.0, 0.0, 0.0, measurement.drr);
+        assert!(measurement.within(&target, &Tolerances::default()));
+        let target = RirParams::new(800.0, 50.0, 0.0, 0.0, measurement.drr);
+        assert!(!measurement.within(&target, &Tolerances::default()));
+        assert_eq!(Measurement::measure(&[1.0], sample_rate).rt60, None);
+    }
+
+    #[test]
+    fn test_defaults_within_tolerances() {
+        // Command line defaults, DRR drawn the same way when omitted
+        let mut rng = StdRng::seed_from_u64(2);
+        for seed in 0..5 {
+            let drr = RirParams::random_drr(500.0, &mut rng);
+            let target = RirParams::new(500.0, 50.0, 4.0, 100.0, drr);
+            let simple = ImpulseResponseSimple::new(500.0, 50.0, 4.0, 100.0, drr);
+            let improved =
+                ImpulseResponseImproved::new(500.0, 50.0, 4.0, 100.0, drr);
+            for ir in [
+                simple.generate_with_rng(44100, &mut StdRng::seed_from_u64(seed)),
+                improved.generate_with_rng(44100, &mut StdRng::seed_from_u64(seed)),
+            ] {
+                let measurement = Measurement::measure(&ir, 44100);
+                assert!(
+                    measurement.within(&target, &Tolerances::default()),
+                    "{:?} {:?}",
+                    target,
+                    measurement
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_verification() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let target = RirParams::new(500.0, 50.0, 0.0, 0.0, 0.0);
+        let tolerances = Tolerances {
+            drr: f32::INFINITY,
+            ..Tolerances::default()
+        };
+        let verification =
+            Verification::new(target, 20).with_tolerances(tolerances);
+
+        let generator = Envelope {
+            edt: 50.0,
+            rt60: 500.0,
+        };
+        let report = verification.run(&generator, 16000, &mut rng);
+        assert!(report.passed(), "{:?}", report);
+        assert_eq!(report.rt60.summary.count, 20);
+        assert!(report.rt60.summary.min <= report.rt60.summary.mean);
+        assert!(report.rt60.summary.std > 0.0);
+
+        let generator = Envelope {
+            edt: 50.0,
+            rt60: 1000.0,
+        };
+        let report = verification.run(&generator, 16000, &mut rng);
+        assert!(!report.rt60.passed && !report.passed(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_generators_pass() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let target = RirParams::new(800.0, 100.0, 4.0, 100.0, -4.0);
+        let verification = Verification::new(target, 10);
+        let improved = ImpulseResponseImproved::new(800.0, 100.0, 4.0, 100.0, -4.0);
+        let report = verification.run(&improved, 16000, &mut rng);
+        assert!(report.passed(), "{:?}", report);
+        let simple = ImpulseResponseSimple::new(800.0, 100.0, 4.0, 100.0, -4.0);
+        let report = verification.run(&simple, 16000, &mut rng);
+        assert!(report.passed(), "{:?}", report);
+    }
+}
@@ -0,0 +1,405 @@
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::Path;
+
+use crate::ensemble::EnsembleMode;
+use crate::RirParams;
+
+/// RIFF, fmt and data chunk headers of a plain PCM WAV file [bytes]
+const WAV_HEADER_SIZE: usize = 44;
+/// Same with WAVE_FORMAT_EXTENSIBLE fmt chunk, used for more than 2 channels
+const WAV_EXTENSIBLE_HEADER_SIZE: usize = 68;
+
+/// Generation settings embedded into WAV files, so every impulse describes
+/// itself even when separated from its manifest.
+///
+/// Stored as `key=value;...` comment (ICMT) in the LIST/INFO chunk, with
+/// crate name and version as software (ISFT).
+///
+/// gain_db: output gain [dB]
+/// sample_rate: rate the impulse was generated at before resampling [Hz]
+/// channels: number of generated channels, `iacc` between their tails
+/// ensemble: realizations combined into the impulse, `ensemble_mode` how
+///
+/// Files from versions before these were recorded read as plain mono
+/// single realizations without gain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    pub algo: String,
+    pub params: RirParams,
+    pub seed: Option<u64>,
+    pub version: String,
+    pub gain_db: f32,
+    pub sample_rate: Option<u32>,
+    pub channels: u16,
+    pub iacc: Option<f32>,
+    pub ensemble: u32,
+    pub ensemble_mode: Option<EnsembleMode>,
+}
+
+impl Metadata {
+    /// Metadata of impulse generated by this version of the crate
+    pub fn new(algo: &str, params: RirParams, seed: Option<u64>) -> Self {
+        Self {
+            algo: algo.to_string(),
+            params,
+            seed,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            gain_db: 0.0,
+            sample_rate: None,
+            channels: 1,
+            iacc: None,
+            ensemble: 1,
+            ensemble_mode: None,
+        }
+    }
+
+    pub fn with_gain_db(mut self, gain_db: f32) -> Self {
+        self.gain_db = gain_db;
+        self
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// IACC only applies to more than one channel and is dropped otherwise
+    pub fn with_channels(mut self, channels: u16, iacc: f32) -> Self {
+        self.channels = channels;
+        self.iacc = (channels > 1).then_some(iacc);
+        self
+    }
+
+    /// Mode only applies to more than one realization and is dropped otherwise
+    pub fn with_ensemble(mut self, realizations: u32, mode: EnsembleMode) -> Self {
+        self.ensemble = realizations;
+        self.ensemble_mode = (realizations > 1).then_some(mode);
+        self
+    }
+
+    fn to_comment(&self) -> String {
+        let mut comment = format!(
+            "algo={};rt60={};edt={};itdg={};er_duration={};drr={};version={};\
+             gain_db={};channels={};ensemble={}",
+            self.algo,
+            self.params.rt60,
+            self.params.edt,
+            self.params.itdg,
+            self.params.er_duration,
+            self.params.drr,
+            self.version,
+            self.gain_db,
+            self.channels,
+            self.ensemble
+        );
+        if let Some(seed) = self.seed {
+            comment.push_str(&format!(";seed={}", seed));
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            comment.push_str(&format!(";sr={}", sample_rate));
+        }
+        if let Some(iacc) = self.iacc {
+            comment.push_str(&format!(";iacc={}", iacc));
+        }
+        if let Some(mode) = self.ensemble_mode {
+            comment.push_str(&format!(";ensemble_mode={}", mode.name()));
+        }
+        comment
+    }
+
+    fn from_comment(comment: &str) -> Option<Self> {
+        let fields: std::collections::HashMap<&str, &str> = comment
+            .split(';')
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let number = |key: &str| fields.get(key)?.parse::<f32>().ok();
+        Some(Self {
+            algo: fields.get("algo")?.to_string(),
+            params: RirParams::new(
+                number("rt60")?,
+                number("edt")?,
+                number("itdg")?,
+                number("er_duration")?,
+                number("drr")?,
+            ),
+            seed: fields.get("seed").and_then(|seed| seed.parse().ok()),
+            version: fields.get("version")?.to_string(),
+            gain_db: number("gain_db").unwrap_or(0.0),
+            sample_rate: fields.get("sr").and_then(|sr| sr.parse().ok()),
+            channels: fields
+                .get("channels")
+                .and_then(|channels| channels.parse().ok())
+                .unwrap_or(1),
+            iacc: number("iacc"),
+            ensemble: fields
+                .get("ensemble")
+                .and_then(|ensemble| ensemble.parse().ok())
+                .unwrap_or(1),
+            ensemble_mode: fields
+                .get("ensemble_mode")
+                .and_then(|mode| EnsembleMode::from_name(mode)),
+        })
+    }
+}
+
+/// Store impulse response as 16 bit mono WAV file with embedded metadata
+pub fn write_wav_with_metadata<P: AsRef<Path>>(
+    data: &[f32],
+    sample_rate: u32,
+    metadata: &Metadata,
+    file_name: P,
+) -> Result<(), hound::Error> {
+    write_multichannel_wav_with_metadata(&[data], sample_rate, metadata, file_name)
+}
+
+/// Store equally long channels as one interleaved 16 bit WAV file with
+/// embedded metadata
+pub fn write_multichannel_wav_with_metadata<P: AsRef<Path>>(
+    channels: &[&[f32]],
+    sample_rate: u32,
+    metadata: &Metadata,
+    file_name: P,
+) -> Result<(), hound::Error> {
+    let mut wav = Cursor::new(Vec::new());
+    write_interleaved(channels, sample_rate, &mut wav)?;
+    let mut wav = wav.into_inner();
+
+    // LIST/INFO chunk goes after data, readers skip chunks they don't know
+    push_chunk(&mut wav, b"LIST", &info_chunk(metadata));
+
+    let riff_size = (wav.len() - 8) as u32;
+    wav[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    std::fs::write(file_name, wav)?;
+    Ok(())
+}
+
+/// Size of the file written by `write_wav_with_metadata` or
+/// `write_multichannel_wav_with_metadata` [bytes], `num_samples` per channel
+pub fn wav_size(num_samples: usize, channels: usize, metadata: &Metadata) -> usize {
+    let header = if channels > 2 {
+        WAV_EXTENSIBLE_HEADER_SIZE
+    } else {
+        WAV_HEADER_SIZE
+    };
+    let info = info_chunk(metadata).len();
+    header + 2 * num_samples * channels + 8 + info + info % 2
+}
+
+/// Metadata embedded by `write_wav_with_metadata`, `None` for other files
+pub fn read_metadata<P: AsRef<Path>>(
+    file_name: P,
+) -> Result<Option<Metadata>, hound::Error> {
+    let wav = std::fs::read(file_name)?;
+    if wav.len() < 12 || &wav[..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err(hound::Error::FormatError("no RIFF WAVE header"));
+    }
+    for (id, data) in chunks(&wav[12..]) {
+        if id == b"LIST" && data.starts_with(b"INFO") {
+            for (id, data) in chunks(&data[4..]) {
+                if id == b"ICMT" {
+                    let comment = String::from_utf8_lossy(data);
+                    return Ok(Metadata::from_comment(
+                        comment.trim_end_matches('\0'),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn info_chunk(metadata: &Metadata) -> Vec<u8> {
+    let mut info = b"INFO".to_vec();
+    let software = format!("{} {}", env!("CARGO_PKG_NAME"), metadata.version);
+    push_chunk(&mut info, b"ISFT", &zero_terminated(&software));
+    push_chunk(&mut info, b"ICMT", &zero_terminated(&metadata.to_comment()));
+    info
+}
+
+/// Iterate over RIFF chunks as (id, data) pairs
+fn chunks(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+        let size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let end = (8 + size).min(data.len());
+        let chunk = (&data[..4], &data[8..end]);
+        // Chunks are padded to even size
+        data = &data[(end + size % 2).min(data.len())..];
+        Some(chunk)
+    })
+}
+
+fn push_chunk(buffer: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    buffer.extend_from_slice(id);
+    buffer.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        buffer.push(0);
+    }
+}
+
+fn zero_terminated(text: &str) -> Vec<u8> {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+/// Store impulse response as 16 bit mono WAV file
+pub fn write_wav<P: AsRef<Path>>(
+    data: &[f32],
+    sample_rate: u32,
+    file_name: P,
+) -> Result<(), hound::Error> {
+    let file = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+    write_wav_to(data, sample_rate, file)
+}
+
+/// Write impulse response as 16 bit mono WAV into any seekable writer
+pub fn write_wav_to<W: Write + Seek>(
+    data: &[f32],
+    sample_rate: u32,
+    writer: W,
+) -> Result<(), hound::Error> {
+    write_interleaved(&[data], sample_rate, writer)
+}
+
+fn write_interleaved<W: Write + Seek>(
+    channels: &[&[f32]],
+    sample_rate: u32,
+    writer: W,
+) -> Result<(), hound::Error> {
+    let len = channels.first().map_or(0, |channel| channel.len());
+    if channels.iter().any(|channel| channel.len() != len) {
+        return Err(hound::Error::Unsupported);
+    }
+    let spec = hound::WavSpec {
+        channels: channels.len() as u16,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let max_amplitude = i16::MAX as f32;
+    let mut writer = hound::WavWriter::new(writer, spec)?;
+    for i in 0..len {
+        for channel in channels {
+            let amplitude = (channel[i] * max_amplitude).round() as i16;
+            writer.write_sample(amplitude)?;
+        }
+    }
+
+    writer.finalize()
+}
+
+/// Load first channel of WAV file scaled to [-1.0, 1.0], with its sample rate
+pub fn read_wav<P: AsRef<Path>>(
+    file_name: P,
+) -> Result<(Vec<f32>, u32), hound::Error> {
+    let file = std::io::BufReader::new(std::fs::File::open(file_name)?);
+    read_wav_from(file)
+}
+
+/// Read first channel of WAV data scaled to [-1.0, 1.0], with its sample rate
+pub fn read_wav_from<R: Read>(reader: R) -> Result<(Vec<f32>, u32), hound::Error> {
+    let mut reader = hound::WavReader::new(reader)?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .step_by(channels)
+            .collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let max_amplitude = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .step_by(channels)
+                .map(|s| s.map(|s| s as f32 / max_amplitude))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    Ok((samples, spec.sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_wav() {
+        let path = std::env::temp_dir().join("storir_test_write_and_read.wav");
+        let data = vec![1.0, 0.5, -0.25, 0.0];
+        write_wav(&data, 16000, &path).unwrap();
+        let (loaded, sample_rate) = read_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(sample_rate, 16000);
+        for (expected, result) in data.iter().zip(&loaded) {
+            assert!((expected - result).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let path = std::env::temp_dir().join("storir_test_metadata.wav");
+        let params = RirParams::new(500.0, 50.0, 0.5, 100.0, -2.25);
+        let metadata = Metadata::new("improved", params, Some(42))
+            .with_gain_db(-6.0)
+            .with_sample_rate(48000)
+            .with_channels(2, 0.3)
+            .with_ensemble(8, EnsembleMode::MedianEdc);
+        let data = vec![1.0, 0.5, -0.25, 0.0, 0.125];
+        write_wav_with_metadata(&data, 16000, &metadata, &path).unwrap();
+        let size = std::fs::metadata(&path).unwrap().len() as usize;
+        assert_eq!(size, wav_size(data.len(), 1, &metadata));
+
+        let loaded = read_metadata(&path).unwrap();
+        let (samples, sample_rate) = read_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, Some(metadata));
+        assert_eq!(sample_rate, 16000);
+
+        // Comments written before gain, rate, channels and ensemble
+        let old = Metadata::from_comment(
+            "algo=simple;rt60=500;edt=50;itdg=4;er_duration=100;drr=-3;\
+             version=0.1.3;seed=7",
+        )
+        .unwrap();
+        let expected = Metadata {
+            version: "0.1.3".to_string(),
+            ..Metadata::new("simple", old.params, Some(7))
+        };
+        assert_eq!(old, expected);
+        assert_eq!(samples.len(), data.len());
+    }
+
+    #[test]
+    fn test_multichannel_wav() {
+        let path = std::env::temp_dir().join("storir_test_multichannel.wav");
+        let params = RirParams::new(500.0, 50.0, 0.5, 100.0, -2.25);
+        let metadata = Metadata::new("improved", params, None);
+        let first = [1.0, 0.5, 0.0];
+        let second = [1.0, -0.5, 0.25];
+        let third = [0.0, 0.0, 0.0];
+        for channels in [vec![&first[..], &second], vec![&first, &second, &third]] {
+            write_multichannel_wav_with_metadata(
+                &channels, 16000, &metadata, &path,
+            )
+            .unwrap();
+
+            let reader = hound::WavReader::open(&path).unwrap();
+            assert_eq!(reader.spec().channels as usize, channels.len());
+            let (samples, _) = read_wav(&path).unwrap();
+            let size = std::fs::metadata(&path).unwrap().len() as usize;
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(samples, vec![1.0 - 1.0 / 32768.0, 0.5, 0.0]);
+            assert_eq!(size, wav_size(3, channels.len(), &metadata));
+        }
+    }
+}